@@ -1,18 +1,29 @@
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, ResourceSpecifier};
-use rdkafka::client::DefaultClientContext;
 use rdkafka::error::KafkaError;
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::util::Timeout;
+use std::sync::Arc;
 use tracing::{event, instrument, Level};
 
-type DefaultAdminClient = AdminClient<DefaultClientContext>;
+use crate::config::{CompressionType, EffectiveConfig};
+use crate::context::{ContextAdapter, RedpandaContext, TracingContext};
+use crate::error::RedpandaError;
+use crate::mock::RedpandaMockCluster;
 
-pub struct RedpandaAdminClient {
-    admin_client: DefaultAdminClient,
+/// Admin client whose callbacks go to a RedpandaContext
+pub type ContextAdminClient<C> = AdminClient<ContextAdapter<C>>;
+
+pub struct RedpandaAdminClient<C: RedpandaContext = TracingContext> {
+    admin_client: ContextAdminClient<C>,
+    /// Set when connected to a mock cluster, which doesn't implement the admin API
+    mock: Option<Arc<RedpandaMockCluster>>,
+    effective_config: EffectiveConfig,
 }
 
-impl RedpandaAdminClient {
+impl<C: RedpandaContext> RedpandaAdminClient<C> {
     /// Construct a new RedpandaAdminClient
     #[instrument(skip(admin_client))]
-    pub async fn new(admin_client: DefaultAdminClient) -> Result<Self, KafkaError> {
+    pub async fn new(admin_client: ContextAdminClient<C>) -> Result<Self, KafkaError> {
         let opts = AdminOptions::new();
         let configs = ResourceSpecifier::Topic("_schemas");
         match admin_client.describe_configs([&configs], &opts).await {
@@ -22,11 +33,55 @@ impl RedpandaAdminClient {
             Err(e) => return Err(e),
         };
 
-        Ok(Self { admin_client })
+        Ok(Self {
+            admin_client,
+            mock: None,
+            effective_config: EffectiveConfig::default(),
+        })
+    }
+
+    /// Construct a RedpandaAdminClient whose topic operations go through a mock cluster
+    ///
+    /// Mock brokers don't answer describe_configs, so the connection is checked with a metadata
+    /// request instead
+    #[instrument(skip(admin_client, mock))]
+    pub(crate) fn with_mock_cluster(
+        admin_client: ContextAdminClient<C>,
+        mock: Arc<RedpandaMockCluster>,
+        request_timeout: Timeout,
+    ) -> Result<Self, KafkaError> {
+        admin_client
+            .inner()
+            .fetch_metadata(Option::None, request_timeout)?;
+        event!(Level::INFO, "Connected admin client to mock cluster");
+
+        Ok(Self {
+            admin_client,
+            mock: Some(mock),
+            effective_config: EffectiveConfig::default(),
+        })
+    }
+
+    pub(crate) fn with_effective_config(mut self, config: EffectiveConfig) -> Self {
+        self.effective_config = config;
+
+        self
+    }
+
+    /// The context receiving this admin client's callbacks
+    pub fn context(&self) -> &Arc<C> {
+        self.admin_client.inner().context().context()
+    }
+
+    /// The properties this admin client was built with, secrets redacted
+    ///
+    /// Empty unless the admin client was built by a RedpandaBuilder
+    pub fn effective_config(&self) -> &EffectiveConfig {
+        &self.effective_config
     }
 
     // TODO: This is unexpectedly broken...librdkafka will return successful topic creation but not actually create the topic...
-    /// Configure and create a topic
+    /// Configure and create a zstd-compressed topic
     #[instrument(skip(self))]
     pub async fn create_topic(
         &self,
@@ -34,11 +89,39 @@ impl RedpandaAdminClient {
         num_partitions: u16,
         replication_factor: u16,
     ) -> Result<(), KafkaError> {
+        self.create_topic_with_compression(
+            name,
+            num_partitions,
+            replication_factor,
+            Some(CompressionType::Zstd),
+        )
+        .await
+    }
+
+    /// Configure and create a topic with the given compression type
+    ///
+    /// compression: None keeps batches compressed however the producer compressed them
+    #[instrument(skip(self))]
+    pub async fn create_topic_with_compression(
+        &self,
+        name: &str,
+        num_partitions: u16,
+        replication_factor: u16,
+        compression: Option<CompressionType>,
+    ) -> Result<(), KafkaError> {
+        if let Some(mock) = &self.mock {
+            return mock.create_topic(name, num_partitions, replication_factor);
+        }
+
         let opts = AdminOptions::new();
         // Fixed replication = all partitions have the same replication factor
         let replication = rdkafka::admin::TopicReplication::Fixed(replication_factor.into());
+        let compression = match compression {
+            Some(compression) => compression.to_string(),
+            None => "producer".to_owned(),
+        };
         let config = vec![
-            ("compression.type", "zstd"),
+            ("compression.type", compression.as_str()),
             ("auto.offset.reset", "beginning"),
         ];
 
@@ -78,9 +161,72 @@ impl RedpandaAdminClient {
         }
     }
 
+    /// Get a topic's compression type, None if it keeps the producer's compression
+    ///
+    /// Mock clusters can't describe topics, so this always fails with UnsupportedFeature on them
+    #[instrument(skip(self))]
+    pub async fn topic_compression_type(
+        &self,
+        name: &str,
+    ) -> Result<Option<CompressionType>, RedpandaError> {
+        if self.mock.is_some() {
+            return Err(KafkaError::AdminOp(RDKafkaErrorCode::UnsupportedFeature).into());
+        }
+
+        let opts = AdminOptions::new();
+        let resource = ResourceSpecifier::Topic(name);
+        let results = self
+            .admin_client
+            .describe_configs([&resource], &opts)
+            .await?;
+        // Since we're only describing one topic, we can safely just match the first element
+        let config = match &results[0] {
+            Ok(config) => config,
+            Err(e) => return Err(KafkaError::AdminOp(*e).into()),
+        };
+
+        match config
+            .get("compression.type")
+            .and_then(|e| e.value.as_deref())
+        {
+            Some("producer") | None => Ok(None),
+            Some(codec) => Ok(Some(codec.parse()?)),
+        }
+    }
+
+    /// Check that a topic stores batches compressed with `producer_compression` as produced,
+    /// rather than having the broker recompress them with a different codec
+    #[instrument(skip(self))]
+    pub async fn check_topic_compression(
+        &self,
+        name: &str,
+        producer_compression: CompressionType,
+    ) -> Result<(), RedpandaError> {
+        let topic_compression = self.topic_compression_type(name).await?;
+        match topic_compression {
+            Some(topic_compression)
+                if !producer_compression.matches_topic(Some(topic_compression)) =>
+            {
+                Err(RedpandaError::CompressionMismatch {
+                    topic: name.to_owned(),
+                    producer: producer_compression,
+                    topic_compression,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Delete a topic
+    ///
+    /// Mock clusters can't delete topics, so this always fails with UnsupportedFeature on them
     #[instrument(skip(self))]
     pub async fn delete_topic(&self, name: &str) -> Result<(), KafkaError> {
+        if self.mock.is_some() {
+            event!(Level::ERROR, "Mock clusters don't support deleting topics");
+            return Err(KafkaError::AdminOp(RDKafkaErrorCode::UnsupportedFeature));
+        }
+
         let opts = AdminOptions::new();
         match self.admin_client.delete_topics(&[name], &opts).await {
             Ok(results_vec) => {
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use futures::future;
+use rdkafka::{
+    consumer::{BaseConsumer, Consumer},
+    error::KafkaError,
+    message::{BorrowedMessage, OwnedMessage},
+    producer::{BaseProducer, Producer},
+    util::Timeout,
+};
+use tracing::{event, instrument, Level};
+
+use crate::codec::{Deserializer, TypedMessage};
+use crate::config::EffectiveConfig;
+use crate::context::{ContextAdapter, RedpandaContext, TracingContext};
+use crate::delivery::{DeliveryStats, PendingDelivery};
+use crate::error::RedpandaError;
+use crate::interceptor::{self, Interceptors};
+use crate::metadata::{check_connection, check_subscription, RedpandaMetadata};
+use crate::partitioner::Partitioner;
+use crate::producer::{
+    base_record, delivered, retry_queue_full, DeliveredRecord, DeliveryFuture, RedpandaRecord,
+    PARTITION_COUNT_TIMEOUT, PARTITION_COUNT_TTL,
+};
+use crate::role::ClientRole;
+
+/// Producer whose delivery reports are served by polling it
+pub type ContextBaseProducer<C> = BaseProducer<ContextAdapter<C>>;
+
+/// Consumer whose callbacks are served by polling it
+pub type ContextBaseConsumer<C> = BaseConsumer<ContextAdapter<C>>;
+
+/// How long a blocking call waits for delivery reports at a time
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Synchronous producer for code without a tokio runtime, e.g. CLI tools or FFI callbacks
+///
+/// Sends block the calling thread until their records are delivered, serving delivery
+/// reports meanwhile. Records go through the builder's interceptors and partitioner, and
+/// delivery events and stats are kept like RedpandaProducer's. Spools, claim checks,
+/// encryption, rate limits and circuit breakers are async and only apply to RedpandaProducer.
+pub struct BlockingProducer<C: RedpandaContext = TracingContext> {
+    pub producer: ContextBaseProducer<C>,
+    request_timeout: Timeout,
+    effective_config: EffectiveConfig,
+    interceptors: Interceptors,
+    partitioner: Option<Arc<dyn Partitioner>>,
+    partition_counts: Mutex<HashMap<String, (i32, Instant)>>,
+}
+
+impl<C: RedpandaContext> BlockingProducer<C> {
+    /// Create a new BlockingProducer, validating that the brokers respond to connections
+    /// within timeout
+    #[instrument(skip(producer))]
+    pub fn new(
+        producer: ContextBaseProducer<C>,
+        request_timeout: Timeout,
+    ) -> Result<Self, KafkaError> {
+        check_connection(producer.client(), ClientRole::Producer, request_timeout)?;
+
+        Ok(Self {
+            producer,
+            request_timeout,
+            effective_config: EffectiveConfig::default(),
+            interceptors: Arc::new([]),
+            partitioner: None,
+            partition_counts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+
+        self
+    }
+
+    /// Pick partitions with `partitioner` for records that don't set one
+    pub fn with_partitioner(mut self, partitioner: Arc<dyn Partitioner>) -> Self {
+        self.partitioner = Some(partitioner);
+
+        self
+    }
+
+    pub(crate) fn with_effective_config(mut self, config: EffectiveConfig) -> Self {
+        self.effective_config = config;
+
+        self
+    }
+
+    /// The context receiving this producer's callbacks
+    pub fn context(&self) -> &Arc<C> {
+        self.producer.context().context()
+    }
+
+    /// The properties this producer was built with, secrets redacted
+    ///
+    /// Empty unless the producer was built by a RedpandaBuilder
+    pub fn effective_config(&self) -> &EffectiveConfig {
+        &self.effective_config
+    }
+
+    /// Delivery counters per topic since the producer was created
+    pub fn delivery_stats(&self) -> HashMap<String, DeliveryStats> {
+        self.producer.context().deliveries().stats()
+    }
+
+    pub fn fetch_metadata(&self) -> Result<RedpandaMetadata, KafkaError> {
+        let metadata = self
+            .producer
+            .client()
+            .fetch_metadata(None, self.request_timeout)?
+            .into();
+
+        Ok(metadata)
+    }
+
+    /// Number of partitions of `topic` from the cluster's metadata, cached like
+    /// RedpandaProducer's
+    ///
+    /// `None` if the topic doesn't exist. A cache miss blocks for up to the request timeout or
+    /// 10 seconds, whichever is shorter.
+    pub fn partition_count(&self, topic: &str) -> Result<Option<i32>, KafkaError> {
+        if let Some((count, fetched)) = self.partition_counts.lock().unwrap().get(topic) {
+            if fetched.elapsed() < PARTITION_COUNT_TTL {
+                return Ok(Some(*count));
+            }
+        }
+        let timeout = match self.request_timeout {
+            Timeout::After(timeout) => timeout.min(PARTITION_COUNT_TIMEOUT),
+            Timeout::Never => PARTITION_COUNT_TIMEOUT,
+        };
+        let metadata: RedpandaMetadata = self
+            .producer
+            .client()
+            .fetch_metadata(Some(topic), timeout)?
+            .into();
+        let count = metadata.partition_count(topic);
+        let mut partition_counts = self.partition_counts.lock().unwrap();
+        match count {
+            Some(count) => partition_counts.insert(topic.to_owned(), (count, Instant::now())),
+            None => partition_counts.remove(topic),
+        };
+
+        Ok(count)
+    }
+
+    /// The record's own partition, else the partitioner's choice, else None to leave it to
+    /// librdkafka
+    fn choose_partition(&self, record: &RedpandaRecord) -> Result<Option<i32>, KafkaError> {
+        let partitioner = match (record.partition(), &self.partitioner) {
+            (Some(partition), _) => return Ok(Some(partition)),
+            (None, None) => return Ok(None),
+            (None, Some(partitioner)) => partitioner,
+        };
+
+        Ok(self
+            .partition_count(record.topic())?
+            .map(|count| partitioner.partition(record.topic(), record.key(), count)))
+    }
+
+    /// Send `record` and block until it's delivered
+    pub fn send(&self, record: &RedpandaRecord) -> Result<DeliveredRecord, RedpandaError> {
+        self.send_all(std::slice::from_ref(record))
+            .pop()
+            .expect("one result per record")
+    }
+
+    /// Queue every record, then block until they're all delivered, returning each record's
+    /// result in the order of `records`
+    ///
+    /// When librdkafka's queue is full, serves delivery reports before retrying, failing the
+    /// record with QueueFull after QUEUE_FULL_RETRIES retries. Other errors fail only the record
+    /// they're for.
+    pub fn send_all(
+        &self,
+        records: &[RedpandaRecord],
+    ) -> Vec<Result<DeliveredRecord, RedpandaError>> {
+        let mut results: Vec<Option<Result<DeliveredRecord, RedpandaError>>> =
+            records.iter().map(|_| None).collect();
+        let mut in_flight = Vec::new();
+        for (i, record) in records.iter().enumerate() {
+            match self.enqueue(record) {
+                Ok(delivery) => in_flight.push((i, delivery)),
+                Err(e) => results[i] = Some(Err(e)),
+            }
+        }
+
+        while !in_flight.is_empty() {
+            self.producer.poll(POLL_INTERVAL);
+            in_flight.retain_mut(|(i, delivery)| {
+                let result = match delivery.rx.try_recv() {
+                    Ok(None) => return true,
+                    Ok(Some(result)) => Ok(result),
+                    Err(oneshot::Canceled) => Err(oneshot::Canceled),
+                };
+                results[*i] = Some(delivered(&records[*i], result));
+                false
+            });
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("every record is sent or failed"))
+            .collect()
+    }
+
+    /// Run `record` through the interceptors, pick its partition and queue it
+    fn enqueue(&self, record: &RedpandaRecord) -> Result<DeliveryFuture, RedpandaError> {
+        let intercepted;
+        let record = match self.interceptors.is_empty() {
+            true => record,
+            false => {
+                intercepted = interceptor::on_send(&self.interceptors, record.clone())?;
+                &intercepted
+            }
+        };
+        let partition = self.choose_partition(record)?;
+        let send = || {
+            let (pending, delivery) = PendingDelivery::intercepted();
+            self.producer
+                .send(base_record(record.into(), partition, pending))
+                .map(|()| delivery)
+                .map_err(|(e, _)| e)
+        };
+        // Nothing to await: the backoff is spent serving delivery reports
+        let serve = |backoff| {
+            self.producer.poll(backoff);
+            future::ready(())
+        };
+
+        Ok(futures::executor::block_on(retry_queue_full(send, serve))?)
+    }
+
+    /// Serve delivery reports for up to `timeout`
+    pub fn poll(&self, timeout: Duration) {
+        self.producer.poll(timeout);
+    }
+
+    /// Block until every queued record is delivered or `timeout` passes
+    pub fn flush(&self, timeout: Duration) -> Result<(), KafkaError> {
+        self.producer.flush(timeout)
+    }
+
+    /// Records and requests not delivered yet
+    pub fn in_flight_count(&self) -> i32 {
+        self.producer.in_flight_count()
+    }
+}
+
+/// Synchronous consumer for code without a tokio runtime, e.g. CLI tools or FFI callbacks
+///
+/// Messages are received by polling with a timeout. `recv_processed` and `recv_typed` run the
+/// builder's interceptors; claim checks and encryption are async and only apply to
+/// RedpandaConsumer.
+pub struct BlockingConsumer<C: RedpandaContext = TracingContext> {
+    pub consumer: ContextBaseConsumer<C>,
+    request_timeout: Timeout,
+    effective_config: EffectiveConfig,
+    interceptors: Interceptors,
+}
+
+impl<C: RedpandaContext> BlockingConsumer<C> {
+    /// Create a new BlockingConsumer, validating that the brokers respond to connections
+    /// within timeout
+    #[instrument(skip(consumer))]
+    pub fn new(
+        consumer: ContextBaseConsumer<C>,
+        request_timeout: Timeout,
+    ) -> Result<Self, KafkaError> {
+        check_connection(consumer.client(), ClientRole::Consumer, request_timeout)?;
+
+        Ok(Self {
+            consumer,
+            request_timeout,
+            effective_config: EffectiveConfig::default(),
+            interceptors: Arc::new([]),
+        })
+    }
+
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+
+        self
+    }
+
+    pub(crate) fn with_effective_config(mut self, config: EffectiveConfig) -> Self {
+        self.effective_config = config;
+
+        self
+    }
+
+    /// The context receiving this consumer's callbacks
+    pub fn context(&self) -> &Arc<C> {
+        self.consumer.context().context()
+    }
+
+    /// The properties this consumer was built with, secrets redacted
+    ///
+    /// Empty unless the consumer was built by a RedpandaBuilder
+    pub fn effective_config(&self) -> &EffectiveConfig {
+        &self.effective_config
+    }
+
+    pub fn fetch_metadata(&self) -> Result<RedpandaMetadata, KafkaError> {
+        let metadata = self
+            .consumer
+            .fetch_metadata(None, self.request_timeout)?
+            .into();
+
+        Ok(metadata)
+    }
+
+    /// Subscribe the consumer to an array of topic names, checking that the topic names are valid
+    ///
+    /// Subsequent calls will replace existing topics and only subscribe to the new topics provided
+    #[instrument(skip(self))]
+    pub fn subscribe(&self, topic_names: &[&str]) -> Result<(), KafkaError> {
+        check_subscription(&self.fetch_metadata()?, topic_names)?;
+        self.consumer.subscribe(topic_names)?;
+        event!(Level::INFO, "Subscribed to topics {:?}", topic_names);
+
+        Ok(())
+    }
+
+    /// Get the names of the currently subscribed topics
+    pub fn get_subscription_topic_names(&self) -> Result<Vec<String>, KafkaError> {
+        let topic_partition_list = self.consumer.subscription()?;
+
+        Ok(topic_partition_list
+            .elements()
+            .iter()
+            .map(|elem| elem.topic().to_owned())
+            .collect())
+    }
+
+    /// Wait up to `timeout` for a message
+    ///
+    /// The interceptors don't apply, see `recv_processed`
+    pub fn recv(&self, timeout: Duration) -> Option<Result<BorrowedMessage<'_>, KafkaError>> {
+        self.consumer.poll(timeout)
+    }
+
+    /// Wait up to `timeout` for a message, run through the interceptors
+    pub fn recv_processed(&self, timeout: Duration) -> Result<Option<OwnedMessage>, RedpandaError> {
+        match self.consumer.poll(timeout) {
+            None => Ok(None),
+            Some(message) => {
+                interceptor::on_consume(&self.interceptors, message?.detach()).map(Some)
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for a message and decode its key and payload
+    ///
+    /// A message that fails to decode is still consumed; the RedpandaError::Deserialize says
+    /// where it is
+    pub fn recv_typed<K, V>(
+        &self,
+        timeout: Duration,
+        key_deserializer: &impl Deserializer<K>,
+        payload_deserializer: &impl Deserializer<V>,
+    ) -> Result<Option<TypedMessage<K, V>>, RedpandaError> {
+        match self.recv_processed(timeout)? {
+            None => Ok(None),
+            Some(message) => {
+                TypedMessage::decode(&message, key_deserializer, payload_deserializer).map(Some)
+            }
+        }
+    }
+}
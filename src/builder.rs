@@ -1,45 +1,99 @@
-use rdkafka::consumer::StreamConsumer;
+use rdkafka::admin::AdminClient;
+use rdkafka::client::Client;
+use rdkafka::client::DefaultClientContext;
+use rdkafka::consumer::{BaseConsumer, Consumer};
 use rdkafka::error::KafkaError;
+use rdkafka::producer::{BaseProducer, Producer};
 use rdkafka::util::Timeout;
 use rdkafka::ClientContext;
 use std::fmt::{Debug, Display};
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::{event, instrument, Level};
 
 use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::producer::ProducerContext;
 
-use crate::admin::RedpandaAdminClient;
-use crate::config::CompressionType;
-use crate::consumer::RedpandaConsumer;
-use crate::RedpandaProducer;
-
-#[derive(Debug)]
-pub struct RedpandaBuilder {
-    client_config: ClientConfig,
+use crate::admin::{ContextAdminClient, RedpandaAdminClient};
+use crate::blocking::{
+    BlockingConsumer, BlockingProducer, ContextBaseConsumer, ContextBaseProducer,
+};
+use crate::claim_check::{ClaimCheck, ObjectStore};
+use crate::config::{CompressionType, EffectiveConfig, RedpandaConfig};
+use crate::consumer::{ContextConsumer, RedpandaConsumer};
+use crate::context::{ContextAdapter, RedpandaContext, TracingContext};
+#[cfg(feature = "encryption")]
+use crate::encryption::Encryption;
+use crate::error::{invalid_config, RedpandaError};
+use crate::interceptor::{Interceptor, Interceptors};
+use crate::mock::RedpandaMockCluster;
+use crate::oauth::{self, OAuthContext, TokenProvider};
+use crate::partitioner::Partitioner;
+use crate::producer::{ContextProducer, RedpandaProducer};
+use crate::profile::{ConfigChange, Profile};
+use crate::role::{ClientRole, ScopedConfig};
+use crate::security::{PemSource, SaslMechanism, SecurityConfig, SecurityProtocol};
+use crate::spool::Spool;
+use crate::throttle::{CircuitBreaker, RateLimiter};
+
+/// TLS/SASL settings live in `security` and are merged in at build time; every part of the
+/// builder redacts secrets in its Debug output
+///
+/// Each client is built from the common settings minus the keys its role doesn't use, then the
+/// security settings, then the role's ScopedConfig; see `effective_config`
+///
+/// Every client built shares the builder's RedpandaContext, TracingContext unless one is given
+/// with `with_context`
+pub struct RedpandaBuilder<C: RedpandaContext = TracingContext> {
+    settings: EffectiveConfig,
+    log_level: RDKafkaLogLevel,
+    security: SecurityConfig,
+    producer_config: ScopedConfig,
+    consumer_config: ScopedConfig,
+    admin_config: ScopedConfig,
     creation_timeout: Timeout,
+    mock: Option<Arc<RedpandaMockCluster>>,
+    profile_changes: Vec<ConfigChange>,
+    partitioner: Option<Arc<dyn Partitioner>>,
+    spool: Option<Arc<Spool>>,
+    claim_check: Option<Arc<ClaimCheck>>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Arc<Encryption>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    context: Arc<C>,
 }
 
-// A simple context to customize the producer behavior and emit a trace every time
-// a message is produced
-pub struct TracingProducerContext;
-
-impl ClientContext for TracingProducerContext {}
-
-impl ProducerContext for TracingProducerContext {
-    type DeliveryOpaque = ();
-
-    fn delivery(
-        &self,
-        delivery_result: &rdkafka::producer::DeliveryResult<'_>,
-        _delivery_opaque: Self::DeliveryOpaque,
-    ) {
-        match delivery_result {
-            Ok(m) => event!(Level::INFO, "Produced {:?}", m),
-            Err(e) => {
-                event!(Level::ERROR, "Failed to produce message {:?} {}", e.1, e.0);
-            }
-        }
+impl<C: RedpandaContext> Debug for RedpandaBuilder<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("RedpandaBuilder");
+        debug
+            .field("settings", &self.settings)
+            .field("log_level", &self.log_level)
+            .field("security", &self.security)
+            .field("producer_config", &self.producer_config)
+            .field("consumer_config", &self.consumer_config)
+            .field("admin_config", &self.admin_config)
+            .field("creation_timeout", &self.creation_timeout)
+            .field("mock", &self.mock)
+            .field("profile_changes", &self.profile_changes)
+            .field("partitioner", &self.partitioner)
+            .field("spool", &self.spool.as_ref().map(|spool| spool.path()))
+            .field("claim_check", &self.claim_check)
+            .field("rate_limiter", &self.rate_limiter)
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field(
+                "interceptors",
+                &self
+                    .interceptors
+                    .iter()
+                    .map(|interceptor| interceptor.name())
+                    .collect::<Vec<_>>(),
+            );
+        #[cfg(feature = "encryption")]
+        debug.field("encryption", &self.encryption);
+        debug.field("context", &std::any::type_name::<C>()).finish()
     }
 }
 
@@ -88,48 +142,452 @@ impl Default for RedpandaBuilder {
 
 impl RedpandaBuilder {
     pub fn new() -> Self {
-        let mut client_config = ClientConfig::new();
-        client_config.set("client.id", "redpanda-rs");
-        client_config.set("group.id", "default-group");
+        let mut settings = EffectiveConfig::default();
+        settings.set("client.id", "redpanda-rs");
+        settings.set("group.id", "default-group");
         // From Redpanda console inferred Kafka version
-        client_config.set("broker.version.fallback", "0.10.2.0");
+        settings.set("broker.version.fallback", "0.10.2.0");
         let creation_timeout = Timeout::Never;
         Self {
-            client_config,
+            settings,
+            log_level: ClientConfig::new().log_level,
+            security: SecurityConfig::default(),
+            producer_config: ScopedConfig::new(ClientRole::Producer),
+            consumer_config: ScopedConfig::new(ClientRole::Consumer),
+            admin_config: ScopedConfig::new(ClientRole::Admin),
             creation_timeout,
+            mock: None,
+            profile_changes: Vec::new(),
+            partitioner: None,
+            spool: None,
+            claim_check: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            rate_limiter: None,
+            circuit_breaker: None,
+            interceptors: Vec::new(),
+            context: Arc::new(TracingContext),
+        }
+    }
+
+    /// Default builder with `profile` applied on top
+    ///
+    /// See Profile for what each profile sets and its trade-offs
+    pub fn profile(profile: Profile) -> Self {
+        let mut builder = Self::default();
+        builder.apply_profile(profile);
+
+        builder
+    }
+
+    /// Default builder connected to a fresh in-process cluster of `num_brokers` librdkafka mock
+    /// brokers, for running without a real Redpanda cluster
+    ///
+    /// Like a new Redpanda cluster, the mock cluster starts with the `_schemas` and
+    /// `__consumer_offsets` topics. It shuts down when the builder and every clone of `mock()`
+    /// are dropped.
+    pub fn mock_cluster(num_brokers: u16) -> Result<Self, RedpandaError> {
+        let mock = RedpandaMockCluster::new(num_brokers)?;
+        let replication_factor = num_brokers.min(3);
+        mock.create_topic("_schemas", 1, replication_factor)?;
+        mock.create_topic("__consumer_offsets", 3, replication_factor)?;
+
+        let mut builder = Self::default();
+        builder.set_bootstrap_servers(mock.bootstrap_servers());
+        builder.mock = Some(Arc::new(mock));
+
+        Ok(builder)
+    }
+
+    /// Default builder with `config` applied on top
+    pub fn from_config(config: &RedpandaConfig) -> Self {
+        let mut builder = Self::default();
+        builder.apply_config(config);
+
+        builder
+    }
+
+    /// Default builder configured from `<PREFIX>_*` environment variables
+    ///
+    /// See RedpandaConfig::from_env for the variable names
+    pub fn from_env(prefix: &str) -> Result<Self, RedpandaError> {
+        Ok(Self::from_config(&RedpandaConfig::from_env(prefix)?))
+    }
+
+    /// Default builder configured from a TOML or YAML file
+    ///
+    /// See RedpandaConfig for the file format
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, RedpandaError> {
+        Ok(Self::from_config(&RedpandaConfig::from_file(path)?))
+    }
+
+    /// Default builder configured from the kafka_api section of an rpk profile
+    ///
+    /// Uses the profile named `profile`, or the file's current profile if `None`
+    pub fn from_rpk_profile(
+        path: impl AsRef<Path>,
+        profile: Option<&str>,
+    ) -> Result<Self, RedpandaError> {
+        Ok(Self::from_config(&RedpandaConfig::from_rpk_profile(
+            path, profile,
+        )?))
+    }
+}
+
+impl<C: RedpandaContext> RedpandaBuilder<C> {
+    /// Builder with the same settings whose clients send their callbacks to `context`
+    ///
+    /// The context is shared by every client built afterwards
+    pub fn with_context<D: RedpandaContext>(self, context: D) -> RedpandaBuilder<D> {
+        RedpandaBuilder {
+            settings: self.settings,
+            log_level: self.log_level,
+            security: self.security,
+            producer_config: self.producer_config,
+            consumer_config: self.consumer_config,
+            admin_config: self.admin_config,
+            creation_timeout: self.creation_timeout,
+            mock: self.mock,
+            profile_changes: self.profile_changes,
+            partitioner: self.partitioner,
+            spool: self.spool,
+            claim_check: self.claim_check,
+            #[cfg(feature = "encryption")]
+            encryption: self.encryption,
+            rate_limiter: self.rate_limiter,
+            circuit_breaker: self.circuit_breaker,
+            interceptors: self.interceptors,
+            context: Arc::new(context),
+        }
+    }
+
+    /// Builder with the same settings whose clients authenticate with SASL/OAUTHBEARER tokens
+    /// from `provider`, refreshed before they expire
+    ///
+    /// Sets the SASL mechanism to OAUTHBEARER; the security protocol still has to be set to a
+    /// SASL protocol. Callbacks other than token refresh still go to the current context.
+    pub fn with_token_provider(
+        mut self,
+        provider: impl TokenProvider,
+    ) -> RedpandaBuilder<OAuthContext<C>> {
+        self.security.sasl_mechanism = Some(SaslMechanism::OAuthBearer);
+        let context = OAuthContext::new(self.context.clone(), Arc::new(provider));
+
+        self.with_context(context)
+    }
+
+    /// The context clients built by this builder send their callbacks to
+    pub fn context(&self) -> &Arc<C> {
+        &self.context
+    }
+
+    /// Apply a profile's settings, overriding the builder's current values
+    ///
+    /// The values it changed are recorded and can be inspected with `profile_diff`
+    pub fn apply_profile(&mut self, profile: Profile) -> &mut RedpandaBuilder<C> {
+        for (key, value) in profile.settings() {
+            let before = self.settings.get_secret(key).map(str::to_owned);
+            if before.as_deref() != Some(*value) {
+                self.profile_changes.push(ConfigChange {
+                    key: key.to_string(),
+                    before,
+                    after: value.to_string(),
+                });
+                self.settings.set(*key, *value);
+            }
+        }
+        event!(Level::DEBUG, "Applied profile {:?}", profile);
+
+        self
+    }
+
+    /// Config values changed by the profiles applied to this builder, in the order they changed
+    pub fn profile_diff(&self) -> &[ConfigChange] {
+        &self.profile_changes
+    }
+
+    /// The mock cluster this builder connects to, to create topics and inject errors and latency
+    ///
+    /// None unless the builder was made with RedpandaBuilder::mock_cluster
+    pub fn mock(&self) -> Option<&Arc<RedpandaMockCluster>> {
+        self.mock.as_ref()
+    }
+
+    /// Apply every setting present in `config`, overriding the builder's current values
+    pub fn apply_config(&mut self, config: &RedpandaConfig) -> &mut RedpandaBuilder<C> {
+        if let Some(servers) = &config.bootstrap_servers {
+            self.set_bootstrap_servers(servers);
+        }
+        if let Some(client_id) = &config.client_id {
+            self.set_client_id(client_id);
+        }
+        if let Some(group_id) = &config.group_id {
+            self.set_group_id(group_id);
         }
+        if let Some(protocol) = config.security_protocol {
+            self.set_security_protocol(protocol);
+        }
+        if let Some(path) = &config.ssl_ca_location {
+            self.set_ssl_ca_location(path);
+        }
+        if let Some(path) = &config.ssl_certificate_location {
+            self.set_ssl_certificate_location(path);
+        }
+        if let Some(path) = &config.ssl_key_location {
+            self.set_ssl_key_location(path);
+        }
+        if let Some(password) = &config.ssl_key_password {
+            self.set_ssl_key_password(password);
+        }
+        if let Some(mechanism) = config.sasl_mechanism {
+            self.set_sasl_mechanism(mechanism);
+        }
+        if let Some(username) = &config.sasl_username {
+            self.security.sasl_username = Some(username.clone());
+        }
+        if let Some(password) = &config.sasl_password {
+            self.security.sasl_password = Some(password.clone());
+        }
+        for (key, value) in &config.properties {
+            self.set(key, value);
+        }
+
+        self
+    }
+
+    /// Settings applied only to producers, over the common settings
+    ///
+    /// ```ignore
+    /// builder.producer_config().set("client.id", "my-service-producer")?;
+    /// ```
+    pub fn producer_config(&mut self) -> &mut ScopedConfig {
+        &mut self.producer_config
+    }
+
+    /// Settings applied only to consumers, over the common settings
+    pub fn consumer_config(&mut self) -> &mut ScopedConfig {
+        &mut self.consumer_config
+    }
+
+    /// Settings applied only to admin clients, over the common settings
+    pub fn admin_config(&mut self) -> &mut ScopedConfig {
+        &mut self.admin_config
+    }
+
+    /// The properties a client of `role` would be built with: defaults, profiles and common
+    /// settings it uses, then the validated security settings, then the role's overrides
+    ///
+    /// Secrets are redacted, so the result can be logged or serialized to JSON
+    pub fn effective_config(&self, role: ClientRole) -> Result<EffectiveConfig, RedpandaError> {
+        Ok(self.role_config(role)?)
+    }
+
+    fn role_config(&self, role: ClientRole) -> Result<EffectiveConfig, KafkaError> {
+        let mut config = self.settings.clone();
+        role.drop_foreign_keys(&mut config);
+        self.security.apply(&mut config)?;
+        match role {
+            ClientRole::Producer => {
+                self.producer_config.apply(&mut config);
+                check_compression_level(&config)?;
+            }
+            ClientRole::Consumer => self.consumer_config.apply(&mut config),
+            ClientRole::Admin => self.admin_config.apply(&mut config),
+        }
+        if oauth::uses_token_refresh::<C>(&config) {
+            config.set("enable_sasl_queue", "true");
+        }
+
+        Ok(config)
+    }
+
+    /// Start serving the context's token refreshes for a client built from `config`
+    fn enable_token_refresh<X: ClientContext>(
+        &self,
+        config: &EffectiveConfig,
+        client: &Client<X>,
+    ) -> Result<(), KafkaError> {
+        if oauth::uses_token_refresh::<C>(config) {
+            oauth::enable_background_refresh(client)?;
+        }
+
+        Ok(())
+    }
+
+    /// The compression type producers built by this builder use, if it's set
+    pub fn compression_type(&self) -> Option<CompressionType> {
+        let producer_config = self.role_config(ClientRole::Producer).ok()?;
+        let codec = producer_config
+            .get("compression.type")
+            .or_else(|| producer_config.get("compression.codec"))?;
+
+        codec.parse().ok()
+    }
+
+    /// Built a RedpandaProducer from the builder's config
+    #[instrument]
+    pub fn build_producer(&self) -> Result<RedpandaProducer<C>, RedpandaError> {
+        let config = self.role_config(ClientRole::Producer)?;
+        let producer: ContextProducer<C> =
+            config.client_config(self.log_level).create_with_context(
+                ContextAdapter::new(self.context.clone()).with_interceptors(self.interceptors()),
+            )?;
+        self.enable_token_refresh(&config, producer.client())?;
+
+        let mut producer = RedpandaProducer::new(producer, self.creation_timeout)?
+            .with_effective_config(config)
+            .with_interceptors(self.interceptors());
+        if let Some(partitioner) = &self.partitioner {
+            producer = producer.with_partitioner(partitioner.clone());
+        }
+        if let Some(spool) = &self.spool {
+            producer = producer.with_spool(spool.clone());
+        }
+        if let Some(claim_check) = &self.claim_check {
+            producer = producer.with_claim_check(claim_check.clone());
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            producer = producer.with_encryption(encryption.clone());
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            producer = producer.with_rate_limiter(rate_limiter.clone());
+        }
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            producer = producer.with_circuit_breaker(circuit_breaker.clone());
+        }
+
+        Ok(producer)
     }
 
-    /// Built a RedpandaProducer from the builder's client_config
+    /// Built a RedpandaConsumer from the builder's config
     #[instrument]
-    pub fn build_producer(&self) -> Result<RedpandaProducer, KafkaError> {
-        let producer_context = TracingProducerContext {};
-        let producer = self
-            .client_config
-            .create_with_context(producer_context)
-            .expect("Producer creation failed");
+    pub fn build_consumer(&self) -> Result<RedpandaConsumer<C>, RedpandaError> {
+        let config = self.role_config(ClientRole::Consumer)?;
+        let consumer: ContextConsumer<C> = config
+            .client_config(self.log_level)
+            .create_with_context(ContextAdapter::new(self.context.clone()))?;
+        self.enable_token_refresh(&config, consumer.client())?;
+
+        let mut consumer = RedpandaConsumer::new(consumer, self.creation_timeout)?
+            .with_effective_config(config)
+            .with_interceptors(self.interceptors());
+        if let Some(claim_check) = &self.claim_check {
+            consumer = consumer.with_claim_check(claim_check.clone());
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            consumer = consumer.with_encryption(encryption.clone());
+        }
 
-        RedpandaProducer::new(producer, self.creation_timeout)
+        Ok(consumer)
     }
 
-    /// Built a RedpandaConsumer from the builder's client_config
+    /// Built a BlockingProducer from the builder's producer config, for code without a tokio
+    /// runtime
+    ///
+    /// Only the builder's interceptors and partitioner apply; fails with AsyncOnly if a spool,
+    /// claim check, encryption, rate limiter or circuit breaker is set. See BlockingProducer
     #[instrument]
-    pub fn build_consumer(&self) -> Result<RedpandaConsumer, KafkaError> {
-        let consumer: StreamConsumer = self
-            .client_config
-            .create()
-            .expect("Consumer creation failed");
-        RedpandaConsumer::new(consumer, self.creation_timeout)
+    pub fn build_blocking_producer(&self) -> Result<BlockingProducer<C>, RedpandaError> {
+        self.check_blocking(ClientRole::Producer)?;
+        let config = self.role_config(ClientRole::Producer)?;
+        let producer: ContextBaseProducer<C> =
+            config.client_config(self.log_level).create_with_context(
+                ContextAdapter::new(self.context.clone()).with_interceptors(self.interceptors()),
+            )?;
+        self.enable_token_refresh(&config, producer.client())?;
+
+        let mut producer = BlockingProducer::new(producer, self.creation_timeout)?
+            .with_effective_config(config)
+            .with_interceptors(self.interceptors());
+        if let Some(partitioner) = &self.partitioner {
+            producer = producer.with_partitioner(partitioner.clone());
+        }
+
+        Ok(producer)
     }
 
-    /// Built a RedpandaAdminClient from the builder's client_config
+    /// Built a BlockingConsumer from the builder's consumer config, for code without a tokio
+    /// runtime
+    ///
+    /// Only the builder's interceptors apply; fails with AsyncOnly if a claim check or
+    /// encryption is set. See BlockingConsumer
     #[instrument]
-    pub async fn build_admin_client(&self) -> Result<RedpandaAdminClient, KafkaError> {
-        let admin_client = self
-            .client_config
-            .create()
-            .expect("AdminClient creation failed");
-        RedpandaAdminClient::new(admin_client).await
+    pub fn build_blocking_consumer(&self) -> Result<BlockingConsumer<C>, RedpandaError> {
+        self.check_blocking(ClientRole::Consumer)?;
+        let config = self.role_config(ClientRole::Consumer)?;
+        let consumer: ContextBaseConsumer<C> = config
+            .client_config(self.log_level)
+            .create_with_context(ContextAdapter::new(self.context.clone()))?;
+        self.enable_token_refresh(&config, consumer.client())?;
+
+        Ok(BlockingConsumer::new(consumer, self.creation_timeout)?
+            .with_effective_config(config)
+            .with_interceptors(self.interceptors()))
+    }
+
+    /// Fail if a setting that only the async clients apply to `role` is set, rather than
+    /// ignore it
+    fn check_blocking(&self, role: ClientRole) -> Result<(), RedpandaError> {
+        let mut async_only = vec![("set_claim_check", self.claim_check.is_some())];
+        #[cfg(feature = "encryption")]
+        async_only.push(("set_encryption", self.encryption.is_some()));
+        if role == ClientRole::Producer {
+            async_only.extend([
+                ("set_spool", self.spool.is_some()),
+                ("set_rate_limiter", self.rate_limiter.is_some()),
+                ("set_circuit_breaker", self.circuit_breaker.is_some()),
+            ]);
+        }
+        match async_only.into_iter().find(|(_, set)| *set) {
+            Some((setting, _)) => Err(RedpandaError::AsyncOnly { setting, role }),
+            None => Ok(()),
+        }
+    }
+
+    /// Built a RedpandaAdminClient from the builder's config
+    #[instrument]
+    pub async fn build_admin_client(&self) -> Result<RedpandaAdminClient<C>, RedpandaError> {
+        let config = self.role_config(ClientRole::Admin)?;
+        let admin_client: ContextAdminClient<C> = config
+            .client_config(self.log_level)
+            .create_with_context(ContextAdapter::new(self.context.clone()))?;
+        self.enable_token_refresh(&config, admin_client.inner())?;
+
+        let admin_client = match &self.mock {
+            Some(mock) => RedpandaAdminClient::with_mock_cluster(
+                admin_client,
+                mock.clone(),
+                self.creation_timeout,
+            )?,
+            None => RedpandaAdminClient::new(admin_client).await?,
+        };
+
+        Ok(admin_client.with_effective_config(config))
+    }
+
+    /// Check that librdkafka accepts the builder's config for producers, consumers and admin
+    /// clients, without connecting to any broker
+    ///
+    /// bootstrap.servers is removed before creating throwaway clients, so nothing is dialed
+    #[instrument]
+    pub fn validate(&self) -> Result<(), RedpandaError> {
+        let _: BaseProducer = self.dry_run_config(ClientRole::Producer)?.create()?;
+        let _: BaseConsumer = self.dry_run_config(ClientRole::Consumer)?.create()?;
+        let _: AdminClient<DefaultClientContext> =
+            self.dry_run_config(ClientRole::Admin)?.create()?;
+        event!(Level::DEBUG, "Validated client config");
+
+        Ok(())
+    }
+
+    /// librdkafka config for `role` without bootstrap.servers
+    fn dry_run_config(&self, role: ClientRole) -> Result<ClientConfig, KafkaError> {
+        let mut config = self.role_config(role)?;
+        config.remove("bootstrap.servers");
+
+        Ok(config.client_config(self.log_level))
     }
 
     /////////////////////////////////////////////////////////////////////////////
@@ -140,8 +598,8 @@ impl RedpandaBuilder {
     /////////////////////////////////////////////////////////////////////////////
 
     /// Set an arbitrary configuration parameter not explicitly defined below
-    pub fn set(&mut self, key: &str, value: &str) -> &mut RedpandaBuilder {
-        self.client_config.set(key, value);
+    pub fn set(&mut self, key: &str, value: &str) -> &mut RedpandaBuilder<C> {
+        self.settings.set(key, value);
 
         self
     }
@@ -149,8 +607,8 @@ impl RedpandaBuilder {
     /// Set the Broker URLs to connect to
     ///
     /// servers: `host:port,host:port`
-    pub fn set_bootstrap_servers(&mut self, servers: &str) -> &mut RedpandaBuilder {
-        self.client_config.set("bootstrap.servers", servers);
+    pub fn set_bootstrap_servers(&mut self, servers: &str) -> &mut RedpandaBuilder<C> {
+        self.settings.set("bootstrap.servers", servers);
 
         self
     }
@@ -159,8 +617,92 @@ impl RedpandaBuilder {
     /// in the original produce order.
     ///
     /// Default: False
-    pub fn enable_idempotence(&mut self) -> &mut RedpandaBuilder {
-        self.client_config.set("enable.idempotence", "true");
+    pub fn enable_idempotence(&mut self) -> &mut RedpandaBuilder<C> {
+        self.settings.set("enable.idempotence", "true");
+
+        self
+    }
+
+    /// Pick partitions for records that don't set one with `partitioner` instead of librdkafka's
+    /// `partitioner` property
+    ///
+    /// Use Murmur2Partitioner to partition keyed records like the Java client
+    pub fn set_partitioner(&mut self, partitioner: impl Partitioner) -> &mut RedpandaBuilder<C> {
+        self.partitioner = Some(Arc::new(partitioner));
+
+        self
+    }
+
+    /// Spool records producers can't deliver with `send_or_spool` to `spool`
+    ///
+    /// Every producer built afterwards shares the spool
+    pub fn set_spool(&mut self, spool: Spool) -> &mut RedpandaBuilder<C> {
+        self.spool = Some(Arc::new(spool));
+
+        self
+    }
+
+    /// Move payloads longer than `threshold` bytes to `store`, sending a reference in their
+    /// place
+    ///
+    /// Producers and consumers built afterwards check payloads in and out; see ClaimCheck
+    pub fn set_claim_check(
+        &mut self,
+        store: impl ObjectStore,
+        threshold: usize,
+    ) -> &mut RedpandaBuilder<C> {
+        self.claim_check = Some(Arc::new(ClaimCheck::new(store, threshold)));
+
+        self
+    }
+
+    /// Run `interceptor` in producers and consumers built afterwards, after the interceptors
+    /// added before it
+    pub fn add_interceptor(&mut self, interceptor: impl Interceptor) -> &mut RedpandaBuilder<C> {
+        self.interceptors.push(Arc::new(interceptor));
+
+        self
+    }
+
+    fn interceptors(&self) -> Interceptors {
+        self.interceptors.iter().cloned().collect()
+    }
+
+    /// Delay records producers send over `rate_limiter`'s limits
+    ///
+    /// Every producer built afterwards shares the limits
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) -> &mut RedpandaBuilder<C> {
+        self.rate_limiter = Some(Arc::new(rate_limiter));
+
+        self
+    }
+
+    /// Fail sends fast once `circuit_breaker` opens
+    ///
+    /// Every producer built afterwards shares the circuit breaker
+    pub fn set_circuit_breaker(
+        &mut self,
+        circuit_breaker: CircuitBreaker,
+    ) -> &mut RedpandaBuilder<C> {
+        self.circuit_breaker = Some(Arc::new(circuit_breaker));
+
+        self
+    }
+
+    /// Encrypt payloads in producers and decrypt them in consumers built afterwards
+    #[cfg(feature = "encryption")]
+    pub fn set_encryption(&mut self, encryption: Encryption) -> &mut RedpandaBuilder<C> {
+        self.encryption = Some(Arc::new(encryption));
+
+        self
+    }
+
+    /// Make producers transactional, identified across restarts by `transactional_id`
+    ///
+    /// Each producer instance needs its own id; a new producer calling init_transactions with
+    /// the same id fences off the old one. Requires idempotence, which is on by default.
+    pub fn set_transactional_id(&mut self, transactional_id: &str) -> &mut RedpandaBuilder<C> {
+        self.settings.set("transactional.id", transactional_id);
 
         self
     }
@@ -174,8 +716,8 @@ impl RedpandaBuilder {
     /// problem is that you're stuck at the end of the partition.
     ///
     /// Default: False
-    pub fn enable_partition_eof(&mut self) -> &mut RedpandaBuilder {
-        self.client_config.set("enable.partition.eof", "true");
+    pub fn enable_partition_eof(&mut self) -> &mut RedpandaBuilder<C> {
+        self.settings.set("enable.partition.eof", "true");
 
         self
     }
@@ -186,9 +728,20 @@ impl RedpandaBuilder {
     pub fn set_compression_type(
         &mut self,
         compression_type: CompressionType,
-    ) -> &mut RedpandaBuilder {
-        self.client_config
-            .set("compression.type", &compression_type.to_string());
+    ) -> &mut RedpandaBuilder<C> {
+        self.settings
+            .set("compression.type", compression_type.to_string());
+
+        self
+    }
+
+    /// Compression level for the codec set with `set_compression_type`. Higher values compress
+    /// better at the cost of more CPU. Checked against CompressionType::level_range when a
+    /// producer is built.
+    ///
+    /// Default: -1 (codec-dependent default)
+    pub fn set_compression_level(&mut self, level: i32) -> &mut RedpandaBuilder<C> {
+        self.settings.set("compression.level", level.to_string());
 
         self
     }
@@ -200,9 +753,8 @@ impl RedpandaBuilder {
     /// messages and checking 'message->err'.
     ///
     /// Default: largest
-    pub fn set_auto_offset_reset(&mut self, offset: AutoOffsetReset) -> &mut RedpandaBuilder {
-        self.client_config
-            .set("auto.offset.reset", offset.to_string());
+    pub fn set_auto_offset_reset(&mut self, offset: AutoOffsetReset) -> &mut RedpandaBuilder<C> {
+        self.settings.set("auto.offset.reset", offset.to_string());
 
         self
     }
@@ -210,8 +762,8 @@ impl RedpandaBuilder {
     /// Client group session and failure detection timeout.
     ///
     /// Default: 45000ms
-    pub fn set_session_timeout_ms(&mut self, timeout_ms: u32) -> &mut RedpandaBuilder {
-        self.client_config
+    pub fn set_session_timeout_ms(&mut self, timeout_ms: u32) -> &mut RedpandaBuilder<C> {
+        self.settings
             .set("session.timeout.ms", timeout_ms.to_string());
 
         self
@@ -220,8 +772,8 @@ impl RedpandaBuilder {
     /// Set the logging level of rdkafka
     ///
     /// TODO: This doesn't seem to be very effective at getting rdkafka to emit logs...
-    pub fn set_rdkafka_log_level(&mut self, level: RDKafkaLogLevel) -> &mut RedpandaBuilder {
-        self.client_config.set_log_level(level);
+    pub fn set_rdkafka_log_level(&mut self, level: RDKafkaLogLevel) -> &mut RedpandaBuilder<C> {
+        self.log_level = level;
 
         self
     }
@@ -229,8 +781,8 @@ impl RedpandaBuilder {
     /// Default timeout for network requests
     ///
     /// Default: 60000ms
-    pub fn set_socket_timeout_ms(&mut self, timeout_ms: u32) -> &mut RedpandaBuilder {
-        self.client_config
+    pub fn set_socket_timeout_ms(&mut self, timeout_ms: u32) -> &mut RedpandaBuilder<C> {
+        self.settings
             .set("socket.timeout.ms", timeout_ms.to_string());
 
         self
@@ -244,17 +796,26 @@ impl RedpandaBuilder {
     pub fn set_socket_connection_setup_timeout_ms(
         &mut self,
         timeout_ms: u32,
-    ) -> &mut RedpandaBuilder {
-        self.client_config
+    ) -> &mut RedpandaBuilder<C> {
+        self.settings
             .set("socket.connection.setup.timeout.ms", timeout_ms.to_string());
 
         self
     }
 
+    /// Client identifier, sent to brokers with every request
+    ///
+    /// Default: redpanda-rs
+    pub fn set_client_id(&mut self, client_id: &str) -> &mut RedpandaBuilder<C> {
+        self.settings.set("client.id", client_id);
+
+        self
+    }
+
     /// Client group id string. All clients sharing the same group.id belong to the same group.
     /// group_id is a string, not an int
-    pub fn set_group_id(&mut self, group_id: &str) -> &mut RedpandaBuilder {
-        self.client_config.set("group.id", group_id.to_string());
+    pub fn set_group_id(&mut self, group_id: &str) -> &mut RedpandaBuilder<C> {
+        self.settings.set("group.id", group_id.to_string());
 
         self
     }
@@ -263,9 +824,128 @@ impl RedpandaBuilder {
     ///
     /// For consumers, this is the time that fetch_metadata() will wait
     /// For producers, TODO
-    pub fn set_creation_timeout_ms(&mut self, timeout_ms: u64) -> &mut RedpandaBuilder {
+    pub fn set_creation_timeout_ms(&mut self, timeout_ms: u64) -> &mut RedpandaBuilder<C> {
         self.creation_timeout = Timeout::After(Duration::from_millis(timeout_ms));
 
         self
     }
+
+    /// Protocol used to communicate with brokers
+    ///
+    /// Default: plaintext
+    pub fn set_security_protocol(&mut self, protocol: SecurityProtocol) -> &mut RedpandaBuilder<C> {
+        self.security.protocol = Some(protocol);
+
+        self
+    }
+
+    /// Path to the CA certificate(s) used to verify the broker's certificate
+    pub fn set_ssl_ca_location(&mut self, path: &str) -> &mut RedpandaBuilder<C> {
+        self.security.ca = Some(PemSource::Location(path.to_owned()));
+
+        self
+    }
+
+    /// PEM string of the CA certificate(s) used to verify the broker's certificate
+    pub fn set_ssl_ca_pem(&mut self, pem: &str) -> &mut RedpandaBuilder<C> {
+        self.security.ca = Some(PemSource::Pem(pem.to_owned()));
+
+        self
+    }
+
+    /// Path to the client's public key (PEM) used for mTLS authentication
+    pub fn set_ssl_certificate_location(&mut self, path: &str) -> &mut RedpandaBuilder<C> {
+        self.security.certificate = Some(PemSource::Location(path.to_owned()));
+
+        self
+    }
+
+    /// PEM string of the client's public key used for mTLS authentication
+    pub fn set_ssl_certificate_pem(&mut self, pem: &str) -> &mut RedpandaBuilder<C> {
+        self.security.certificate = Some(PemSource::Pem(pem.to_owned()));
+
+        self
+    }
+
+    /// Path to the client's private key (PEM) used for mTLS authentication
+    pub fn set_ssl_key_location(&mut self, path: &str) -> &mut RedpandaBuilder<C> {
+        self.security.key = Some(PemSource::Location(path.to_owned()));
+
+        self
+    }
+
+    /// PEM string of the client's private key used for mTLS authentication
+    ///
+    /// The key is redacted from this builder's Debug output
+    pub fn set_ssl_key_pem(&mut self, pem: &str) -> &mut RedpandaBuilder<C> {
+        self.security.key = Some(PemSource::Pem(pem.to_owned()));
+
+        self
+    }
+
+    /// Passphrase of the client's private key
+    ///
+    /// The passphrase is redacted from this builder's Debug output
+    pub fn set_ssl_key_password(&mut self, password: &str) -> &mut RedpandaBuilder<C> {
+        self.security.key_password = Some(password.to_owned());
+
+        self
+    }
+
+    /// SASL mechanism to use for authentication. Requires a `Sasl*` security protocol and
+    /// credentials set with `set_sasl_credentials`
+    pub fn set_sasl_mechanism(&mut self, mechanism: SaslMechanism) -> &mut RedpandaBuilder<C> {
+        self.security.sasl_mechanism = Some(mechanism);
+
+        self
+    }
+
+    /// SASL username and password for the PLAIN and SCRAM mechanisms
+    ///
+    /// The password is redacted from this builder's Debug output
+    pub fn set_sasl_credentials(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> &mut RedpandaBuilder<C> {
+        self.security.sasl_username = Some(username.to_owned());
+        self.security.sasl_password = Some(password.to_owned());
+
+        self
+    }
+}
+
+/// Check compression.level is in the range the configured codec accepts
+///
+/// Values librdkafka can't parse at all are left for it to reject
+fn check_compression_level(config: &EffectiveConfig) -> Result<(), KafkaError> {
+    let level = match config.get("compression.level") {
+        Some(level) => level,
+        None => return Ok(()),
+    };
+    let codec = config
+        .get("compression.type")
+        .or_else(|| config.get("compression.codec"))
+        .unwrap_or("none");
+    let (level_value, codec) = match (level.parse::<i32>(), codec.parse::<CompressionType>()) {
+        (Ok(level), Ok(codec)) => (level, codec),
+        _ => return Ok(()),
+    };
+    if level_value == -1 {
+        return Ok(());
+    }
+
+    match codec.level_range() {
+        Some(range) if range.contains(&level_value) => Ok(()),
+        Some(range) => Err(invalid_config(
+            "compression.level",
+            level,
+            &format!("{} accepts levels {:?}", codec, range),
+        )),
+        None => Err(invalid_config(
+            "compression.level",
+            level,
+            &format!("{} has no compression levels", codec),
+        )),
+    }
 }
@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::Debug;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rand::Rng;
+use rdkafka::message::{Message, OwnedMessage};
+use tracing::{event, Level};
+
+use crate::error::RedpandaError;
+use crate::headers::MessageHeaders;
+use crate::producer::RedpandaRecord;
+
+#[cfg(feature = "s3")]
+pub mod s3;
+
+/// Header holding the object store key of a checked-in payload
+pub const CLAIM_CHECK_HEADER: &str = "redpanda.claim-check";
+
+/// Error returned by an ObjectStore
+pub type ObjectStoreError = Box<dyn Error + Send + Sync>;
+
+/// Blob storage for payloads too big to send through the brokers
+///
+/// Keys are `/`-separated paths made of topic names and random hex
+pub trait ObjectStore: Debug + Send + Sync + 'static {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), ObjectStoreError>>;
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Vec<u8>, ObjectStoreError>>;
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), ObjectStoreError>>;
+}
+
+impl<T: ObjectStore + ?Sized> ObjectStore for Arc<T> {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), ObjectStoreError>> {
+        (**self).put(key, bytes)
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Vec<u8>, ObjectStoreError>> {
+        (**self).get(key)
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), ObjectStoreError>> {
+        (**self).delete(key)
+    }
+}
+
+/// Stores objects as files under a root directory
+#[derive(Debug, Clone)]
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    /// Store objects under `root`, which is created on the first put
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_owned(),
+        }
+    }
+
+    /// Path of `key`, refusing keys that would escape the root
+    fn path(&self, key: &str) -> Result<PathBuf, ObjectStoreError> {
+        let relative = Path::new(key);
+        let valid = !key.is_empty()
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if !valid {
+            return Err(format!("invalid object key {:?}", key).into());
+        }
+
+        Ok(self.root.join(relative))
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), ObjectStoreError>> {
+        async move {
+            let path = self.path(key)?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // Readers never see a partly written object
+            let partial = path.with_extension("partial");
+            tokio::fs::write(&partial, bytes).await?;
+            tokio::fs::rename(&partial, &path).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Vec<u8>, ObjectStoreError>> {
+        async move { Ok(tokio::fs::read(self.path(key)?).await?) }.boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), ObjectStoreError>> {
+        async move { Ok(tokio::fs::remove_file(self.path(key)?).await?) }.boxed()
+    }
+}
+
+/// Moves payloads bigger than a threshold to an ObjectStore, sending a CLAIM_CHECK_HEADER
+/// with the object's key and an empty payload instead
+///
+/// Producers check records in and consumers check them out, fetching the payload back.
+/// Objects are never deleted, since any number of consumer groups may read a record; expire
+/// them with the store's lifecycle rules.
+#[derive(Debug, Clone)]
+pub struct ClaimCheck {
+    store: Arc<dyn ObjectStore>,
+    threshold: usize,
+}
+
+impl ClaimCheck {
+    /// Check in payloads longer than `threshold` bytes
+    ///
+    /// Keep `threshold` comfortably below `message.max.bytes`, which also counts the key,
+    /// headers and batch overhead.
+    pub fn new(store: impl ObjectStore, threshold: usize) -> Self {
+        Self {
+            store: Arc::new(store),
+            threshold,
+        }
+    }
+
+    pub fn store(&self) -> &Arc<dyn ObjectStore> {
+        &self.store
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Whether `record`'s payload is over the threshold
+    pub fn is_oversized(&self, record: &RedpandaRecord) -> bool {
+        record.payload().len() > self.threshold
+    }
+
+    /// `record` unchanged if its payload is under the threshold, otherwise a copy whose
+    /// payload was stored
+    pub async fn check_in<'a>(
+        &self,
+        record: &'a RedpandaRecord,
+    ) -> Result<Cow<'a, RedpandaRecord>, RedpandaError> {
+        if !self.is_oversized(record) {
+            return Ok(Cow::Borrowed(record));
+        }
+        let key = format!(
+            "{}/{:032x}",
+            record.topic(),
+            rand::thread_rng().gen::<u128>()
+        );
+        self.store
+            .put(&key, record.payload().to_vec())
+            .await
+            .map_err(|source| RedpandaError::ClaimCheck {
+                key: key.clone(),
+                source,
+            })?;
+        event!(
+            Level::DEBUG,
+            "Checked in {} byte payload as {}",
+            record.payload().len(),
+            key
+        );
+
+        Ok(Cow::Owned(
+            record
+                .clone()
+                .with_payload(Vec::new())
+                .with_header(CLAIM_CHECK_HEADER, key.as_str()),
+        ))
+    }
+
+    /// `message` with its stored payload fetched back, unchanged if it wasn't checked in
+    ///
+    /// The CLAIM_CHECK_HEADER is kept, so the object can be found and deleted later.
+    pub async fn check_out(
+        &self,
+        mut message: OwnedMessage,
+    ) -> Result<OwnedMessage, RedpandaError> {
+        let key = match message.header_map().get_str(CLAIM_CHECK_HEADER) {
+            Some(Ok(key)) => key.to_owned(),
+            _ => return Ok(message),
+        };
+        let payload = self
+            .store
+            .get(&key)
+            .await
+            .map_err(|source| RedpandaError::ClaimCheck {
+                key: key.clone(),
+                source,
+            })?;
+
+        Ok(OwnedMessage::new(
+            Some(payload),
+            message.key().map(<[u8]>::to_vec),
+            message.topic().to_owned(),
+            message.timestamp(),
+            message.partition(),
+            message.offset(),
+            message.detach_headers(),
+        ))
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::Debug;
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use crate::claim_check::{ObjectStore, ObjectStoreError};
+use crate::security::REDACTED;
+
+/// Characters S3 leaves unescaped in a canonical URI, besides the path separators
+fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~')
+}
+
+/// S3 or an S3-compatible store such as MinIO, addressed path-style
+/// (`{endpoint}/{bucket}/{key}`) and authenticated with AWS Signature Version 4
+#[derive(Clone)]
+pub struct S3ObjectStore {
+    client: reqwest::Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl Debug for S3ObjectStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3ObjectStore")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &REDACTED)
+            .finish()
+    }
+}
+
+impl S3ObjectStore {
+    /// Store objects in `bucket` at `endpoint`, e.g. `http://localhost:9000` for MinIO or
+    /// `https://s3.eu-west-1.amazonaws.com`
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key_id: &str,
+        secret_access_key: &str,
+    ) -> Result<Self, ObjectStoreError> {
+        let endpoint = Url::parse(endpoint)?;
+        if endpoint.host_str().is_none() {
+            return Err(format!("S3 endpoint {} has no host", endpoint).into());
+        }
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: bucket.to_owned(),
+            region: region.to_owned(),
+            access_key_id: access_key_id.to_owned(),
+            secret_access_key: secret_access_key.to_owned(),
+        })
+    }
+
+    /// Path of `key`, URI-encoded the way SigV4 canonicalizes it
+    fn path(&self, key: &str) -> String {
+        let mut path = self.endpoint.path().trim_end_matches('/').to_owned();
+        for segment in [self.bucket.as_str()].into_iter().chain(key.split('/')) {
+            path.push('/');
+            path.push_str(&uri_encode(segment));
+        }
+
+        path
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        key: &str,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, ObjectStoreError> {
+        let path = self.path(key);
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let payload_hash = hex(&Sha256::digest(&body));
+        let now = Utc::now();
+        let headers = [
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", &amz_date(now)),
+        ]
+        .map(|(name, value)| (name.to_owned(), value.to_owned()));
+        let authorization = sign(
+            &SigningRequest {
+                method: method.as_str(),
+                path: &path,
+                headers: &headers,
+                payload_hash: &payload_hash,
+            },
+            now,
+            &self.region,
+            &self.access_key_id,
+            &self.secret_access_key,
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let response = request.body(body).send().await?;
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            StatusCode::NOT_FOUND => Err(format!("object {} not found", key).into()),
+            status => {
+                let body = response.text().await.unwrap_or_default();
+                Err(format!("S3 request for {} failed with {}: {}", key, status, body).into())
+            }
+        }
+    }
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        bytes: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), ObjectStoreError>> {
+        async move {
+            self.request(Method::PUT, key, bytes).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Vec<u8>, ObjectStoreError>> {
+        async move {
+            let response = self.request(Method::GET, key, Vec::new()).await?;
+
+            Ok(response.bytes().await?.to_vec())
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), ObjectStoreError>> {
+        async move {
+            self.request(Method::DELETE, key, Vec::new()).await?;
+
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// What SigV4 signs of a request without a query string
+pub(crate) struct SigningRequest<'a> {
+    pub method: &'a str,
+    /// Already URI-encoded
+    pub path: &'a str,
+    /// Lowercase names; `host`, `x-amz-content-sha256` and `x-amz-date` are required
+    pub headers: &'a [(String, String)],
+    pub payload_hash: &'a str,
+}
+
+/// The Authorization header of `request` for S3 in `region`
+pub(crate) fn sign(
+    request: &SigningRequest<'_>,
+    now: DateTime<Utc>,
+    region: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+) -> String {
+    let mut headers = request.headers.to_vec();
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        request.method, request.path, canonical_headers, signed_headers, request.payload_hash
+    );
+
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date(now),
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = [date.as_str(), region, "s3", "aws4_request"].iter().fold(
+        format!("AWS4{}", secret_access_key).into_bytes(),
+        |key, part| hmac(&key, part.as_bytes()),
+    );
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id, scope, signed_headers, signature
+    )
+}
+
+fn amz_date(now: DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match is_unreserved(b) {
+            true => (b as char).to_string(),
+            false => format!("%{:02X}", b),
+        })
+        .collect()
+}
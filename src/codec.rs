@@ -0,0 +1,193 @@
+use std::error::Error;
+use std::fmt::Display;
+
+use rdkafka::message::Message;
+use rdkafka::Timestamp;
+
+use crate::error::RedpandaError;
+
+/// Error returned by a Serializer or Deserializer
+pub type CodecError = Box<dyn Error + Send + Sync>;
+
+/// Turns a `T` into the bytes of a record key or payload
+pub trait Serializer<T: ?Sized> {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, CodecError>;
+}
+
+/// Turns the bytes of a record key or payload back into a `T`
+pub trait Deserializer<T> {
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, CodecError>;
+}
+
+/// Which part of a record a codec failed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RecordPart {
+    Key,
+    Payload,
+    Header,
+}
+
+impl Display for RecordPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordPart::Key => write!(f, "key"),
+            RecordPart::Payload => write!(f, "payload"),
+            RecordPart::Header => write!(f, "header"),
+        }
+    }
+}
+
+/// Passes bytes through unchanged
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RawCodec;
+
+impl Serializer<[u8]> for RawCodec {
+    fn serialize(&self, value: &[u8]) -> Result<Vec<u8>, CodecError> {
+        Ok(value.to_vec())
+    }
+}
+
+impl Serializer<Vec<u8>> for RawCodec {
+    fn serialize(&self, value: &Vec<u8>) -> Result<Vec<u8>, CodecError> {
+        Ok(value.clone())
+    }
+}
+
+impl Deserializer<Vec<u8>> for RawCodec {
+    fn deserialize(&self, bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// UTF-8 strings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StringCodec;
+
+impl Serializer<str> for StringCodec {
+    fn serialize(&self, value: &str) -> Result<Vec<u8>, CodecError> {
+        Ok(value.as_bytes().to_vec())
+    }
+}
+
+impl Serializer<String> for StringCodec {
+    fn serialize(&self, value: &String) -> Result<Vec<u8>, CodecError> {
+        Ok(value.as_bytes().to_vec())
+    }
+}
+
+impl Deserializer<String> for StringCodec {
+    fn deserialize(&self, bytes: &[u8]) -> Result<String, CodecError> {
+        Ok(String::from_utf8(bytes.to_vec())?)
+    }
+}
+
+/// Any serde type as JSON, with the `json` feature
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json")]
+impl<T: serde::Serialize + ?Sized> Serializer<T> for JsonCodec {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Deserializer<T> for JsonCodec {
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Any serde type in bincode's default encoding, with the `bincode` feature
+#[cfg(feature = "bincode")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+#[cfg(feature = "bincode")]
+impl<T: serde::Serialize + ?Sized> Serializer<T> for BincodeCodec {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(bincode::serialize(value)?)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl<T: serde::de::DeserializeOwned> Deserializer<T> for BincodeCodec {
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// Decode the key and payload of consumed messages
+///
+/// Missing keys and payloads, e.g. tombstones, decode to None. Failures are
+/// RedpandaError::Deserialize, carrying the message's position so it can be skipped.
+pub trait DecodeMessage: Message {
+    fn decode_key<T>(
+        &self,
+        deserializer: &impl Deserializer<T>,
+    ) -> Result<Option<T>, RedpandaError> {
+        self.key()
+            .map(|key| decode(self, RecordPart::Key, key, deserializer))
+            .transpose()
+    }
+
+    fn decode_payload<T>(
+        &self,
+        deserializer: &impl Deserializer<T>,
+    ) -> Result<Option<T>, RedpandaError> {
+        self.payload()
+            .map(|payload| decode(self, RecordPart::Payload, payload, deserializer))
+            .transpose()
+    }
+}
+
+impl<M: Message + ?Sized> DecodeMessage for M {}
+
+fn decode<M: Message + ?Sized, T>(
+    message: &M,
+    part: RecordPart,
+    bytes: &[u8],
+    deserializer: &impl Deserializer<T>,
+) -> Result<T, RedpandaError> {
+    deserializer
+        .deserialize(bytes)
+        .map_err(|source| RedpandaError::Deserialize {
+            topic: message.topic().to_owned(),
+            partition: message.partition(),
+            offset: message.offset(),
+            part,
+            source,
+        })
+}
+
+/// A consumed message with its key and payload decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedMessage<K, V> {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub timestamp: Timestamp,
+    pub key: Option<K>,
+    pub payload: Option<V>,
+}
+
+impl<K, V> TypedMessage<K, V> {
+    /// Decode `message` with the given deserializers
+    pub fn decode<M: Message + ?Sized>(
+        message: &M,
+        key_deserializer: &impl Deserializer<K>,
+        payload_deserializer: &impl Deserializer<V>,
+    ) -> Result<Self, RedpandaError> {
+        Ok(Self {
+            topic: message.topic().to_owned(),
+            partition: message.partition(),
+            offset: message.offset(),
+            timestamp: message.timestamp(),
+            key: message.decode_key(key_deserializer)?,
+            payload: message.decode_payload(payload_deserializer)?,
+        })
+    }
+}
@@ -1,21 +1,474 @@
-use std::fmt::Display;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+use rdkafka::config::ClientConfig;
 pub use rdkafka::config::RDKafkaLogLevel;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::error::RedpandaError;
+use crate::security::{is_sensitive_key, SaslMechanism, SecurityProtocol, REDACTED};
 
+/// Compression codec, used for both the producer's and topics' `compression.type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompressionType {
     /// No compression
     None,
-    /// zstd compression
-    Zstd,
     /// gzip compression
     Gzip,
+    /// snappy compression
+    Snappy,
+    /// lz4 compression
+    Lz4,
+    /// zstd compression
+    Zstd,
+}
+
+impl CompressionType {
+    /// Range of `compression.level` values the codec accepts, or None if it has no levels
+    ///
+    /// librdkafka caps every codec at 12, below zstd's own maximum of 22
+    pub fn level_range(&self) -> Option<RangeInclusive<i32>> {
+        match self {
+            CompressionType::None => Option::None,
+            CompressionType::Gzip => Some(0..=9),
+            CompressionType::Snappy => Some(0..=0),
+            CompressionType::Lz4 => Some(0..=12),
+            CompressionType::Zstd => Some(0..=12),
+        }
+    }
+
+    /// Whether messages produced with this codec are stored as produced on a topic with
+    /// `topic_compression` (None: the topic's `compression.type` is `producer`)
+    ///
+    /// Otherwise the broker recompresses every batch with the topic's codec
+    pub fn matches_topic(&self, topic_compression: Option<CompressionType>) -> bool {
+        match topic_compression {
+            Some(topic_compression) => topic_compression == *self,
+            None => true,
+        }
+    }
 }
 
 impl Display for CompressionType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            CompressionType::None => write!(f, ""),
-            CompressionType::Zstd => write!(f, "zstd"),
+            CompressionType::None => write!(f, "none"),
             CompressionType::Gzip => write!(f, "gzip"),
+            CompressionType::Snappy => write!(f, "snappy"),
+            CompressionType::Lz4 => write!(f, "lz4"),
+            CompressionType::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// Parses librdkafka's and topics' codec names; Kafka's `uncompressed` is read as None
+impl FromStr for CompressionType {
+    type Err = RedpandaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "uncompressed" => Ok(CompressionType::None),
+            "gzip" => Ok(CompressionType::Gzip),
+            "snappy" => Ok(CompressionType::Snappy),
+            "lz4" => Ok(CompressionType::Lz4),
+            "zstd" => Ok(CompressionType::Zstd),
+            s => Err(RedpandaError::ClientConfig {
+                key: "compression.type".to_owned(),
+                message: format!("unknown compression type {}", s),
+            }),
         }
     }
 }
+
+/// Serializable RedpandaBuilder settings, loaded from files, the environment or rpk profiles
+///
+/// Every field is optional; unset fields leave the builder's value alone. Sources are combined
+/// with `merge`, where the argument wins. The recommended precedence, lowest to highest, is
+/// rpk profile < config file < environment < setters called on the builder afterwards.
+///
+/// TOML example:
+///
+/// ```toml
+/// bootstrap_servers = "localhost:9010,localhost:9011"
+/// group_id = "my-service"
+/// security_protocol = "sasl_ssl"
+/// sasl_mechanism = "SCRAM-SHA-256"
+/// sasl_username = "my-service"
+/// sasl_password = "..."
+///
+/// [properties]
+/// "socket.timeout.ms" = "3000"
+/// ```
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedpandaConfig {
+    /// Broker URLs, `host:port,host:port`
+    pub bootstrap_servers: Option<String>,
+    pub client_id: Option<String>,
+    pub group_id: Option<String>,
+    pub security_protocol: Option<SecurityProtocol>,
+    pub ssl_ca_location: Option<String>,
+    pub ssl_certificate_location: Option<String>,
+    pub ssl_key_location: Option<String>,
+    pub ssl_key_password: Option<String>,
+    pub sasl_mechanism: Option<SaslMechanism>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    /// Arbitrary librdkafka properties, applied with RedpandaBuilder::set
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+impl RedpandaConfig {
+    /// Load from a TOML (`.toml`) or YAML (`.yaml`, `.yml`) file, picked by extension
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, RedpandaError> {
+        let path = path.as_ref();
+        let origin = path.display().to_string();
+        let contents = read_file(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => from_toml(&contents, &origin),
+            Some("yaml") | Some("yml") => from_yaml(&contents, &origin),
+            _ => Err(RedpandaError::Config {
+                origin,
+                key: String::new(),
+                message: "unsupported file extension, expected .toml, .yaml or .yml".to_owned(),
+            }),
+        }
+    }
+
+    /// Load from `<PREFIX>_<FIELD>` environment variables, e.g. `MYAPP_BOOTSTRAP_SERVERS`
+    ///
+    /// `<PREFIX>_RDKAFKA_<PROPERTY>` variables are added to `properties`, with underscores
+    /// turned into dots: `MYAPP_RDKAFKA_SOCKET_TIMEOUT_MS` sets `socket.timeout.ms`.
+    /// Any other variable starting with `<PREFIX>_` is rejected.
+    pub fn from_env(prefix: &str) -> Result<Self, RedpandaError> {
+        Self::from_vars(prefix, std::env::vars())
+    }
+
+    /// from_env over an explicit set of variables
+    pub(crate) fn from_vars(
+        prefix: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, RedpandaError> {
+        let prefix = format!("{}_", prefix);
+        let property_prefix = format!("{}RDKAFKA_", prefix);
+
+        let mut fields = serde_json::Map::new();
+        let mut properties = BTreeMap::new();
+        for (name, value) in vars {
+            if let Some(property) = name.strip_prefix(&property_prefix) {
+                properties.insert(property.to_lowercase().replace('_', "."), value);
+            } else if let Some(field) = name.strip_prefix(&prefix) {
+                fields.insert(field.to_lowercase(), serde_json::Value::String(value));
+            }
+        }
+
+        let mut config: Self = serde_path_to_error::deserialize(serde_json::Value::Object(fields))
+            .map_err(|e| {
+                let key = format!("{}{}", prefix, e.path().to_string().to_uppercase());
+                RedpandaError::Config {
+                    origin: "environment".to_owned(),
+                    key,
+                    message: e.into_inner().to_string(),
+                }
+            })?;
+        config.properties = properties;
+
+        Ok(config)
+    }
+
+    /// Load the `kafka_api` section of a profile in an rpk profile file (`rpk.yaml`)
+    ///
+    /// Uses the profile named `profile`, or the file's `current_profile` if `None`
+    pub fn from_rpk_profile(
+        path: impl AsRef<Path>,
+        profile: Option<&str>,
+    ) -> Result<Self, RedpandaError> {
+        let origin = path.as_ref().display().to_string();
+        let contents = read_file(path.as_ref())?;
+        let rpk: RpkProfiles = from_yaml(&contents, &origin)?;
+
+        let name = match profile.or(rpk.current_profile.as_deref()) {
+            Some(name) => name,
+            None => {
+                return Err(RedpandaError::Config {
+                    origin,
+                    key: "current_profile".to_owned(),
+                    message: "no profile given and no current profile set".to_owned(),
+                })
+            }
+        };
+        match rpk.profiles.into_iter().find(|p| p.name == name) {
+            Some(p) => Ok(p.kafka_api.into()),
+            None => Err(RedpandaError::Config {
+                origin,
+                key: "profiles".to_owned(),
+                message: format!("no profile named {}", name),
+            }),
+        }
+    }
+
+    /// Load the `rpk.kafka_api` section of a `redpanda.yaml` node config
+    pub fn from_redpanda_yaml(path: impl AsRef<Path>) -> Result<Self, RedpandaError> {
+        let origin = path.as_ref().display().to_string();
+        let contents = read_file(path.as_ref())?;
+        let node: RedpandaYaml = from_yaml(&contents, &origin)?;
+
+        Ok(node.rpk.kafka_api.into())
+    }
+
+    /// Layer `other` over `self`: fields set in `other` replace those in `self`, properties are
+    /// merged key by key
+    pub fn merge(mut self, other: RedpandaConfig) -> Self {
+        fn take<T>(base: &mut Option<T>, over: Option<T>) {
+            if over.is_some() {
+                *base = over;
+            }
+        }
+
+        take(&mut self.bootstrap_servers, other.bootstrap_servers);
+        take(&mut self.client_id, other.client_id);
+        take(&mut self.group_id, other.group_id);
+        take(&mut self.security_protocol, other.security_protocol);
+        take(&mut self.ssl_ca_location, other.ssl_ca_location);
+        take(
+            &mut self.ssl_certificate_location,
+            other.ssl_certificate_location,
+        );
+        take(&mut self.ssl_key_location, other.ssl_key_location);
+        take(&mut self.ssl_key_password, other.ssl_key_password);
+        take(&mut self.sasl_mechanism, other.sasl_mechanism);
+        take(&mut self.sasl_username, other.sasl_username);
+        take(&mut self.sasl_password, other.sasl_password);
+        self.properties.extend(other.properties);
+
+        self
+    }
+}
+
+impl Debug for RedpandaConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedpandaConfig")
+            .field("bootstrap_servers", &self.bootstrap_servers)
+            .field("client_id", &self.client_id)
+            .field("group_id", &self.group_id)
+            .field("security_protocol", &self.security_protocol)
+            .field("ssl_ca_location", &self.ssl_ca_location)
+            .field("ssl_certificate_location", &self.ssl_certificate_location)
+            .field("ssl_key_location", &self.ssl_key_location)
+            .field(
+                "ssl_key_password",
+                &self.ssl_key_password.as_ref().map(|_| REDACTED),
+            )
+            .field("sasl_mechanism", &self.sasl_mechanism)
+            .field("sasl_username", &self.sasl_username)
+            .field(
+                "sasl_password",
+                &self.sasl_password.as_ref().map(|_| REDACTED),
+            )
+            .field("properties", &self.properties.keys())
+            .finish()
+    }
+}
+
+/// The librdkafka properties a client is (or would be) created with
+///
+/// Only properties set by RedpandaBuilder are listed; anything else is at its librdkafka
+/// default. Secret values are replaced with `[redacted]` everywhere this type exposes them,
+/// including Debug and Serialize, so it's safe to log or dump as JSON:
+///
+/// ```ignore
+/// let json = serde_json::to_string_pretty(&producer.effective_config())?;
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct EffectiveConfig {
+    properties: BTreeMap<String, String>,
+}
+
+impl EffectiveConfig {
+    /// Value of `key`, redacted if it's a secret
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties
+            .get_key_value(key)
+            .map(|(key, value)| redact(key, value))
+    }
+
+    /// Properties sorted by key, with secrets redacted
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.properties
+            .iter()
+            .map(|(key, value)| (key.as_str(), redact(key, value)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    pub(crate) fn set(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.properties.insert(key.into(), value.into());
+
+        self
+    }
+
+    /// Unredacted value of `key`, for building clients
+    pub(crate) fn get_secret(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    pub(crate) fn remove(&mut self, key: &str) -> &mut Self {
+        self.properties.remove(key);
+
+        self
+    }
+
+    /// Set every property of `other`, overriding existing values
+    pub(crate) fn extend(&mut self, other: &EffectiveConfig) {
+        self.properties.extend(other.properties.clone());
+    }
+
+    /// librdkafka config holding the unredacted properties
+    pub(crate) fn client_config(&self, log_level: RDKafkaLogLevel) -> ClientConfig {
+        let mut client_config = ClientConfig::new();
+        for (key, value) in &self.properties {
+            client_config.set(key, value);
+        }
+        client_config.set_log_level(log_level);
+
+        client_config
+    }
+}
+
+impl Debug for EffectiveConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl Serialize for EffectiveConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+fn redact<'a>(key: &str, value: &'a str) -> &'a str {
+    match is_sensitive_key(key) {
+        true => REDACTED,
+        false => value,
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, RedpandaError> {
+    std::fs::read_to_string(path).map_err(|source| RedpandaError::ConfigFile {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+fn from_toml<T: DeserializeOwned>(contents: &str, origin: &str) -> Result<T, RedpandaError> {
+    serde_path_to_error::deserialize(toml::Deserializer::new(contents)).map_err(|e| {
+        RedpandaError::Config {
+            origin: origin.to_owned(),
+            key: e.path().to_string(),
+            message: e.into_inner().message().to_owned(),
+        }
+    })
+}
+
+fn from_yaml<T: DeserializeOwned>(contents: &str, origin: &str) -> Result<T, RedpandaError> {
+    serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(contents)).map_err(|e| {
+        RedpandaError::Config {
+            origin: origin.to_owned(),
+            key: e.path().to_string(),
+            message: e.into_inner().to_string(),
+        }
+    })
+}
+
+/// rpk.yaml; only the fields needed to find a profile's kafka_api are read
+#[derive(Deserialize)]
+struct RpkProfiles {
+    current_profile: Option<String>,
+    #[serde(default)]
+    profiles: Vec<RpkProfile>,
+}
+
+#[derive(Deserialize)]
+struct RpkProfile {
+    name: String,
+    #[serde(default)]
+    kafka_api: RpkKafkaApi,
+}
+
+/// redpanda.yaml; only the rpk section is read
+#[derive(Deserialize)]
+struct RedpandaYaml {
+    #[serde(default)]
+    rpk: RpkSection,
+}
+
+#[derive(Deserialize, Default)]
+struct RpkSection {
+    #[serde(default)]
+    kafka_api: RpkKafkaApi,
+}
+
+/// The kafka_api section shared by rpk profiles and redpanda.yaml
+#[derive(Deserialize, Default)]
+struct RpkKafkaApi {
+    #[serde(default)]
+    brokers: Vec<String>,
+    tls: Option<RpkTls>,
+    sasl: Option<RpkSasl>,
+}
+
+#[derive(Deserialize)]
+struct RpkTls {
+    ca_file: Option<String>,
+    cert_file: Option<String>,
+    key_file: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RpkSasl {
+    user: String,
+    password: String,
+    mechanism: SaslMechanism,
+}
+
+/// rpk enables TLS and SASL by the presence of their sections
+impl From<RpkKafkaApi> for RedpandaConfig {
+    fn from(api: RpkKafkaApi) -> Self {
+        let mut config = RedpandaConfig::default();
+        if !api.brokers.is_empty() {
+            config.bootstrap_servers = Some(api.brokers.join(","));
+        }
+
+        config.security_protocol = match (&api.tls, &api.sasl) {
+            (Some(_), Some(_)) => Some(SecurityProtocol::SaslSsl),
+            (Some(_), None) => Some(SecurityProtocol::Ssl),
+            (None, Some(_)) => Some(SecurityProtocol::SaslPlaintext),
+            (None, None) => None,
+        };
+        if let Some(tls) = api.tls {
+            config.ssl_ca_location = tls.ca_file;
+            config.ssl_certificate_location = tls.cert_file;
+            config.ssl_key_location = tls.key_file;
+        }
+        if let Some(sasl) = api.sasl {
+            config.sasl_mechanism = Some(sasl.mechanism);
+            config.sasl_username = Some(sasl.user);
+            config.sasl_password = Some(sasl.password);
+        }
+
+        config
+    }
+}
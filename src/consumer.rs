@@ -1,44 +1,101 @@
+use std::sync::Arc;
+
+use futures::stream::{BoxStream, StreamExt};
 use rdkafka::{
     consumer::{MessageStream, StreamConsumer},
     error::KafkaError,
-    message::BorrowedMessage,
+    message::{BorrowedMessage, OwnedMessage},
     util::Timeout,
 };
 use tracing::{event, instrument, Level};
 
-use crate::metadata::RedpandaMetadata;
+use crate::claim_check::ClaimCheck;
+use crate::codec::{Deserializer, TypedMessage};
+use crate::config::EffectiveConfig;
+use crate::context::{ContextAdapter, RedpandaContext, TracingContext};
+#[cfg(feature = "encryption")]
+use crate::encryption::Encryption;
+use crate::error::RedpandaError;
+use crate::interceptor::{self, Interceptors};
+use crate::metadata::{check_connection, check_subscription, RedpandaMetadata};
+use crate::role::ClientRole;
 
 pub use rdkafka::consumer::Consumer;
 pub use rdkafka::consumer::CommitMode;
+pub use rdkafka::consumer::Rebalance;
+
+/// Consumer whose callbacks go to a RedpandaContext
+pub type ContextConsumer<C> = StreamConsumer<ContextAdapter<C>>;
 
-pub struct RedpandaConsumer {
-    pub consumer: StreamConsumer,
+pub struct RedpandaConsumer<C: RedpandaContext = TracingContext> {
+    pub consumer: ContextConsumer<C>,
     request_timeout: Timeout,
+    effective_config: EffectiveConfig,
+    claim_check: Option<Arc<ClaimCheck>>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Arc<Encryption>>,
+    interceptors: Interceptors,
 }
 
-impl RedpandaConsumer {
+impl<C: RedpandaContext> RedpandaConsumer<C> {
     /// Create a new RedpandaConsumer, validating that the brokers respond to connections within timeout
     #[instrument(skip(consumer))]
-    pub fn new(consumer: StreamConsumer, request_timeout: Timeout) -> Result<Self, KafkaError> {
-        match consumer.fetch_metadata(Option::None, request_timeout) {
-            Ok(m) => {
-                let m: RedpandaMetadata = m.into();
-                event!(
-                    Level::INFO,
-                    "Connected consumer to Redpanda cluster {:?}",
-                    m
-                );
-                m
-            }
-            Err(e) => return Err(e),
-        };
+    pub fn new(consumer: ContextConsumer<C>, request_timeout: Timeout) -> Result<Self, KafkaError> {
+        check_connection(consumer.client(), ClientRole::Consumer, request_timeout)?;
 
         Ok(Self {
             consumer,
             request_timeout,
+            effective_config: EffectiveConfig::default(),
+            claim_check: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            interceptors: Arc::new([]),
         })
     }
 
+    /// Check out claim-checked payloads with `claim_check` in `recv_processed`, `recv_typed` and
+    /// `stream_processed`
+    pub fn with_claim_check(mut self, claim_check: Arc<ClaimCheck>) -> Self {
+        self.claim_check = Some(claim_check);
+
+        self
+    }
+
+    /// Decrypt encrypted payloads with `encryption` in `recv_processed`, `recv_typed` and
+    /// `stream_processed`
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, encryption: Arc<Encryption>) -> Self {
+        self.encryption = Some(encryption);
+
+        self
+    }
+
+    /// Run `interceptors`' `on_consume` in `recv_processed`, `recv_typed` and `stream_processed`
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+
+        self
+    }
+
+    pub(crate) fn with_effective_config(mut self, config: EffectiveConfig) -> Self {
+        self.effective_config = config;
+
+        self
+    }
+
+    /// The context receiving this consumer's callbacks
+    pub fn context(&self) -> &Arc<C> {
+        self.consumer.context().context()
+    }
+
+    /// The properties this consumer was built with, secrets redacted
+    ///
+    /// Empty unless the consumer was built by a RedpandaBuilder
+    pub fn effective_config(&self) -> &EffectiveConfig {
+        &self.effective_config
+    }
+
     /// Get consumer metadata
     pub fn fetch_metadata(&self) -> Result<RedpandaMetadata, KafkaError> {
         let metadata = self
@@ -54,15 +111,7 @@ impl RedpandaConsumer {
     /// Subsequent calls will replace existing topics and only subscribe to the new topics provided
     #[instrument(skip(self))]
     pub fn subscribe(&self, topic_names: &[&str]) -> Result<(), KafkaError> {
-        let cluster_topic_names = self.fetch_metadata()?.topic_names();
-
-        for topic in topic_names {
-            let valid_name = cluster_topic_names.binary_search(&topic.to_owned().to_owned());
-            if valid_name.is_err() {
-                let e = KafkaError::Subscription(format!("Invalid topic name {}", topic));
-                return Err(e);
-            }
-        }
+        check_subscription(&self.fetch_metadata()?, topic_names)?;
 
         match self.consumer.subscribe(topic_names) {
             Ok(_) => {
@@ -85,12 +134,76 @@ impl RedpandaConsumer {
     }
 
     /// Receive a single message
+    ///
+    /// The claim check, encryption and interceptors don't apply, see `recv_processed`
     pub async fn recv(&self) -> Result<BorrowedMessage<'_>, KafkaError> {
         self.consumer.recv().await
     }
 
+    /// Receive a single message, with its payload checked out if it was claim-checked, then
+    /// decrypted if it was encrypted, then run through the interceptors
+    pub async fn recv_processed(&self) -> Result<OwnedMessage, RedpandaError> {
+        let message = self.consumer.recv().await?.detach();
+
+        self.process(message).await
+    }
+
+    /// Receive a single message and decode its key and payload
+    ///
+    /// A message that fails to decode is still consumed; the RedpandaError::Deserialize says
+    /// where it is
+    pub async fn recv_typed<K, V>(
+        &self,
+        key_deserializer: &impl Deserializer<K>,
+        payload_deserializer: &impl Deserializer<V>,
+    ) -> Result<TypedMessage<K, V>, RedpandaError> {
+        let message = match self.transforms_payloads() {
+            true => self.recv_processed().await?,
+            false => {
+                let message = self.recv().await?;
+                return TypedMessage::decode(&message, key_deserializer, payload_deserializer);
+            }
+        };
+
+        TypedMessage::decode(&message, key_deserializer, payload_deserializer)
+    }
+
+    /// Check out, decrypt and intercept a received message
+    async fn process(&self, mut message: OwnedMessage) -> Result<OwnedMessage, RedpandaError> {
+        if let Some(claim_check) = &self.claim_check {
+            message = claim_check.check_out(message).await?;
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            message = encryption.decrypt(message).await?;
+        }
+
+        interceptor::on_consume(&self.interceptors, message)
+    }
+
+    /// Whether received messages have to be checked out, decrypted or intercepted
+    fn transforms_payloads(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.encryption.is_some() {
+            return true;
+        }
+
+        self.claim_check.is_some() || !self.interceptors.is_empty()
+    }
+
     /// Create a message stream from the subscribed topics
-    pub fn stream(&self) -> MessageStream {
+    ///
+    /// The claim check, encryption and interceptors don't apply, see `stream_processed`
+    pub fn stream(&self) -> MessageStream<'_> {
         self.consumer.stream()
     }
+
+    /// Create a message stream from the subscribed topics, of messages processed like
+    /// `recv_processed`'s
+    pub fn stream_processed(&self) -> BoxStream<'_, Result<OwnedMessage, RedpandaError>> {
+        self.consumer
+            .stream()
+            .then(move |message| async move { self.process(message?.detach()).await })
+            .boxed()
+    }
 }
@@ -0,0 +1,279 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use rdkafka::client::OAuthToken;
+use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::consumer::{ConsumerContext, Rebalance};
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{DeliveryResult, ProducerContext};
+use rdkafka::statistics::Statistics;
+use rdkafka::{ClientContext, TopicPartitionList};
+use tokio::sync::broadcast;
+use tracing::{event, Level};
+
+use crate::delivery::{DeliveryReports, PendingDelivery};
+use crate::interceptor::Interceptors;
+
+/// Callbacks from librdkafka for the clients built by a RedpandaBuilder
+///
+/// Every callback has a default, so implementations only override what they need. One context
+/// is shared by every client a builder builds.
+///
+/// Callbacks run on librdkafka's and rdkafka's background threads, not on a tokio task, so they
+/// must not block: send anything slow over a channel, or `spawn` it on a tokio runtime Handle
+/// captured when the context was created. ChannelContext does the former for errors, stats,
+/// logs and rebalances, so async code can `await` them.
+pub trait RedpandaContext: Send + Sync + 'static {
+    /// Whether librdkafka should call `generate_oauth_token` to refresh SASL/OAUTHBEARER tokens
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = false;
+
+    /// A record produced by a RedpandaProducer was delivered or failed for good
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>) {
+        match delivery_result {
+            Ok(m) => event!(Level::INFO, "Produced {:?}", m),
+            Err(e) => {
+                event!(Level::ERROR, "Failed to produce message {:?} {}", e.1, e.0);
+            }
+        }
+    }
+
+    /// A global client error, e.g. every broker is down or authentication failed
+    fn error(&self, error: KafkaError, reason: &str) {
+        event!(Level::ERROR, "librdkafka: {}: {}", error, reason);
+    }
+
+    /// Client statistics, every `statistics.interval.ms` if that's set
+    fn stats(&self, statistics: Statistics) {
+        event!(Level::DEBUG, "Client stats: {:?}", statistics);
+    }
+
+    /// A log line from librdkafka
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        match level {
+            RDKafkaLogLevel::Emerg
+            | RDKafkaLogLevel::Alert
+            | RDKafkaLogLevel::Critical
+            | RDKafkaLogLevel::Error => {
+                event!(target: "librdkafka", Level::ERROR, "librdkafka: {} {}", fac, log_message)
+            }
+            RDKafkaLogLevel::Warning => {
+                event!(target: "librdkafka", Level::WARN, "librdkafka: {} {}", fac, log_message)
+            }
+            RDKafkaLogLevel::Notice | RDKafkaLogLevel::Info => {
+                event!(target: "librdkafka", Level::INFO, "librdkafka: {} {}", fac, log_message)
+            }
+            RDKafkaLogLevel::Debug => {
+                event!(target: "librdkafka", Level::DEBUG, "librdkafka: {} {}", fac, log_message)
+            }
+        }
+    }
+
+    /// A consumer's partitions are about to be assigned or revoked
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        event!(Level::DEBUG, "Rebalancing {:?}", rebalance);
+    }
+
+    /// A consumer's partitions were assigned or revoked
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        event!(Level::INFO, "Rebalanced {:?}", rebalance);
+    }
+
+    /// A fresh SASL/OAUTHBEARER token, called when ENABLE_REFRESH_OAUTH_TOKEN is true
+    ///
+    /// `oauthbearer_config` is the `sasl.oauthbearer.config` property
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        Err("generate_oauth_token is not implemented by this RedpandaContext".into())
+    }
+}
+
+/// The default context: logs deliveries, errors and rebalances with tracing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingContext;
+
+impl RedpandaContext for TracingContext {}
+
+/// Events that can be received from a ChannelContext
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ContextEvent {
+    Error {
+        error: KafkaError,
+        reason: String,
+    },
+    Stats(Box<Statistics>),
+    Log {
+        level: RDKafkaLogLevel,
+        fac: String,
+        message: String,
+    },
+    /// A consumer was assigned these partitions
+    Assigned(TopicPartitionList),
+    /// A consumer's partitions were revoked
+    Revoked(TopicPartitionList),
+    RebalanceError(String),
+}
+
+/// Events buffered per ChannelContext subscriber before the slowest one starts missing events
+const CONTEXT_EVENT_CAPACITY: usize = 1024;
+
+/// Context that hands its callbacks to async code: each one is forwarded to `inner`, then
+/// published as a ContextEvent to every receiver from `subscribe`
+///
+/// Publishing never blocks, so a receiver that falls more than 1024 events behind misses the
+/// oldest ones. Deliveries aren't published; use the producer's `delivery_events` for those.
+pub struct ChannelContext<C: RedpandaContext = TracingContext> {
+    inner: C,
+    events: broadcast::Sender<ContextEvent>,
+}
+
+impl<C: RedpandaContext> ChannelContext<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            events: broadcast::channel(CONTEXT_EVENT_CAPACITY).0,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    /// Receive the events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ContextEvent> {
+        self.events.subscribe()
+    }
+
+    fn publish(&self, event: ContextEvent) {
+        // Only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+}
+
+impl<C: RedpandaContext> RedpandaContext for ChannelContext<C> {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>) {
+        self.inner.delivery(delivery_result);
+    }
+
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.inner.error(error.clone(), reason);
+        self.publish(ContextEvent::Error {
+            error,
+            reason: reason.to_owned(),
+        });
+    }
+
+    fn stats(&self, statistics: Statistics) {
+        self.inner.stats(statistics.clone());
+        self.publish(ContextEvent::Stats(Box::new(statistics)));
+    }
+
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.inner.log(level, fac, log_message);
+        self.publish(ContextEvent::Log {
+            level,
+            fac: fac.to_owned(),
+            message: log_message.to_owned(),
+        });
+    }
+
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.inner.pre_rebalance(rebalance);
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.inner.post_rebalance(rebalance);
+        self.publish(match rebalance {
+            Rebalance::Assign(partitions) => ContextEvent::Assigned((*partitions).clone()),
+            Rebalance::Revoke(partitions) => ContextEvent::Revoked((*partitions).clone()),
+            Rebalance::Error(e) => ContextEvent::RebalanceError(e.clone()),
+        });
+    }
+
+    fn generate_oauth_token(
+        &self,
+        oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        self.inner.generate_oauth_token(oauthbearer_config)
+    }
+}
+
+/// rdkafka context forwarding to a shared RedpandaContext
+///
+/// As a ProducerContext it also completes each record's DeliveryFuture and publishes its
+/// DeliveryEvent
+pub struct ContextAdapter<C: RedpandaContext> {
+    context: Arc<C>,
+    deliveries: DeliveryReports,
+}
+
+impl<C: RedpandaContext> ContextAdapter<C> {
+    pub(crate) fn new(context: Arc<C>) -> Self {
+        Self {
+            context,
+            deliveries: DeliveryReports::default(),
+        }
+    }
+
+    /// Run `interceptors`' `on_ack` for every delivery report
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.deliveries.set_interceptors(interceptors);
+
+        self
+    }
+
+    pub(crate) fn deliveries(&self) -> &DeliveryReports {
+        &self.deliveries
+    }
+
+    /// The RedpandaContext callbacks are forwarded to
+    pub fn context(&self) -> &Arc<C> {
+        &self.context
+    }
+}
+
+impl<C: RedpandaContext> ClientContext for ContextAdapter<C> {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = C::ENABLE_REFRESH_OAUTH_TOKEN;
+
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.context.log(level, fac, log_message);
+    }
+
+    fn stats(&self, statistics: Statistics) {
+        self.context.stats(statistics);
+    }
+
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.context.error(error, reason);
+    }
+
+    fn generate_oauth_token(
+        &self,
+        oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        self.context.generate_oauth_token(oauthbearer_config)
+    }
+}
+
+impl<C: RedpandaContext> ProducerContext for ContextAdapter<C> {
+    type DeliveryOpaque = Box<PendingDelivery>;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>, pending: Self::DeliveryOpaque) {
+        self.context.delivery(delivery_result);
+        self.deliveries.report(delivery_result, *pending);
+    }
+}
+
+impl<C: RedpandaContext> ConsumerContext for ContextAdapter<C> {
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.context.pre_rebalance(rebalance);
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.context.post_rebalance(rebalance);
+    }
+}
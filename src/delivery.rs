@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::channel::oneshot;
+use rdkafka::error::KafkaError;
+use rdkafka::message::{Message, OwnedMessage};
+use rdkafka::producer::future_producer::OwnedDeliveryResult;
+use rdkafka::producer::DeliveryResult;
+use tokio::sync::broadcast;
+
+use crate::interceptor::Interceptors;
+use crate::producer::DeliveryFuture;
+
+/// Delivery events buffered per subscriber before the slowest one starts missing events
+pub const DELIVERY_EVENT_CAPACITY: usize = 1024;
+
+/// Delivery opaque of records sent by a RedpandaProducer: completes the record's DeliveryFuture
+/// and times its delivery
+#[derive(Debug)]
+pub struct PendingDelivery {
+    tx: oneshot::Sender<OwnedDeliveryResult>,
+    sent: Instant,
+    /// Whether the record went through the interceptors' `on_send`
+    intercepted: bool,
+}
+
+impl PendingDelivery {
+    /// A pending delivery sent now, and the future its delivery report completes
+    ///
+    /// Interceptors' `on_ack` skips the record, as it wasn't sent through `on_send`
+    pub fn new() -> (Self, DeliveryFuture) {
+        Self::pending(false)
+    }
+
+    /// A pending delivery for a record sent through the interceptors' `on_send`
+    pub(crate) fn intercepted() -> (Self, DeliveryFuture) {
+        Self::pending(true)
+    }
+
+    fn pending(intercepted: bool) -> (Self, DeliveryFuture) {
+        let (tx, rx) = oneshot::channel();
+        let pending = Self {
+            tx,
+            sent: Instant::now(),
+            intercepted,
+        };
+
+        let delivery = DeliveryFuture {
+            rx,
+            circuit_breaker: None,
+        };
+
+        (pending, delivery)
+    }
+}
+
+/// Outcome of one record sent by a RedpandaProducer
+#[derive(Debug, Clone)]
+pub struct DeliveryEvent {
+    pub topic: String,
+    /// -1 if the record failed before a partition was picked
+    pub partition: i32,
+    /// -1 if the record failed
+    pub offset: i64,
+    /// From the record being sent to its delivery report
+    pub latency: Duration,
+    pub error: Option<KafkaError>,
+    /// The record, only for failed deliveries, e.g. to send it to a dead-letter topic
+    pub message: Option<Arc<OwnedMessage>>,
+}
+
+impl DeliveryEvent {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Delivery counters of one topic
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeliveryStats {
+    pub delivered: u64,
+    pub failed: u64,
+    /// Sum of every delivery's latency, failed ones included
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl DeliveryStats {
+    /// Mean latency of every delivery, zero if there were none
+    pub fn mean_latency(&self) -> Duration {
+        match self.delivered + self.failed {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total_latency.as_nanos() / count as u128) as u64),
+        }
+    }
+
+    fn record(&mut self, event: &DeliveryEvent) {
+        match event.error {
+            None => self.delivered += 1,
+            Some(_) => self.failed += 1,
+        }
+        self.total_latency += event.latency;
+        self.max_latency = self.max_latency.max(event.latency);
+    }
+}
+
+/// Publishes a producer's delivery events, keeps its per-topic counters and runs its
+/// interceptors' `on_ack` for intercepted records
+pub(crate) struct DeliveryReports {
+    events: broadcast::Sender<DeliveryEvent>,
+    stats: Mutex<HashMap<String, DeliveryStats>>,
+    interceptors: Interceptors,
+}
+
+impl Default for DeliveryReports {
+    fn default() -> Self {
+        Self {
+            events: broadcast::channel(DELIVERY_EVENT_CAPACITY).0,
+            stats: Mutex::new(HashMap::new()),
+            interceptors: Arc::new([]),
+        }
+    }
+}
+
+impl DeliveryReports {
+    /// Record a delivery report and complete the record's DeliveryFuture
+    pub(crate) fn report(&self, delivery_result: &DeliveryResult<'_>, pending: PendingDelivery) {
+        let latency = pending.sent.elapsed();
+        let (event, owned_delivery_result) = match delivery_result {
+            Ok(message) => (
+                DeliveryEvent {
+                    topic: message.topic().to_owned(),
+                    partition: message.partition(),
+                    offset: message.offset(),
+                    latency,
+                    error: None,
+                    message: None,
+                },
+                Ok((message.partition(), message.offset())),
+            ),
+            Err((error, message)) => {
+                let owned = message.detach();
+                let event = DeliveryEvent {
+                    topic: message.topic().to_owned(),
+                    partition: message.partition(),
+                    offset: -1,
+                    latency,
+                    error: Some(error.clone()),
+                    message: Some(Arc::new(owned.clone())),
+                };
+                (event, Err((error.clone(), owned)))
+            }
+        };
+        self.stats
+            .lock()
+            .unwrap()
+            .entry(event.topic.clone())
+            .or_default()
+            .record(&event);
+        if pending.intercepted {
+            for interceptor in self.interceptors.iter() {
+                interceptor.on_ack(&event);
+            }
+        }
+        // Only fails when nobody is subscribed
+        let _ = self.events.send(event);
+        // The DeliveryFuture may have been dropped, nobody is waiting for the result then
+        let _ = pending.tx.send(owned_delivery_result);
+    }
+
+    pub(crate) fn set_interceptors(&mut self, interceptors: Interceptors) {
+        self.interceptors = interceptors;
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<DeliveryEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn stats(&self) -> HashMap<String, DeliveryStats> {
+        self.stats.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,481 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rand::RngCore;
+use rdkafka::message::{Message, OwnedMessage};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{event, Level};
+
+use crate::error::RedpandaError;
+use crate::headers::MessageHeaders;
+use crate::producer::RedpandaRecord;
+
+/// Header naming the key encryption key that wrapped the record's data key
+pub const KEY_ID_HEADER: &str = "redpanda.encryption.key-id";
+/// Header holding the record's wrapped data key
+pub const DATA_KEY_HEADER: &str = "redpanda.encryption.data-key";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+/// A data key encrypts records for this long or this many records, whichever comes first, so
+/// the key provider isn't called for every record
+const DATA_KEY_LIFETIME: Duration = Duration::from_secs(300);
+const DATA_KEY_USES: u64 = 1 << 20;
+/// Unwrapped data keys kept by a consumer
+const UNWRAPPED_KEY_CACHE: usize = 1024;
+
+/// Error returned by a KeyProvider
+pub type KeyProviderError = Box<dyn Error + Send + Sync>;
+
+#[derive(Error, Debug)]
+pub enum EncryptionError {
+    #[error("key provider failed")]
+    KeyProvider(#[source] KeyProviderError),
+    #[error("{0} header is missing or invalid")]
+    InvalidHeader(&'static str),
+    #[error("record already has a {0} header")]
+    HeaderPresent(&'static str),
+    #[error("data key unwrapped with key {key_id} is {len} bytes long instead of 32")]
+    InvalidDataKey { key_id: String, len: usize },
+    #[error(
+        "payload failed to decrypt with the data key wrapped by key {key_id}; it was tampered with \
+         or encrypted with a different data key"
+    )]
+    Decryption { key_id: String },
+}
+
+/// A data key encrypted with a key encryption key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    pub key_id: String,
+    pub wrapped: Vec<u8>,
+}
+
+/// Keeps the key encryption keys that wrap records' data keys, e.g. a KMS
+///
+/// Rotating keys only changes `current_key_id`: records carry the id of the key that wrapped
+/// their data key, so keys that were rotated out must still unwrap.
+pub trait KeyProvider: Debug + Send + Sync + 'static {
+    /// Id of the key new data keys are wrapped with
+    fn current_key_id(&self) -> String;
+
+    /// Wrap `data_key` with the current key
+    fn wrap_key<'a>(
+        &'a self,
+        data_key: &'a [u8],
+    ) -> BoxFuture<'a, Result<WrappedKey, KeyProviderError>>;
+
+    /// Unwrap a data key wrapped with key `key_id`
+    fn unwrap_key<'a>(
+        &'a self,
+        key_id: &'a str,
+        wrapped: &'a [u8],
+    ) -> BoxFuture<'a, Result<Vec<u8>, KeyProviderError>>;
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    current: String,
+    /// Hex-encoded AES-256 keys by id
+    keys: BTreeMap<String, String>,
+}
+
+/// Key encryption keys in a local JSON file, for tests and development
+///
+/// The file is created with a single key if it doesn't exist. Keys are stored unencrypted,
+/// so it's no substitute for a KMS. Data keys are wrapped with their key's id as associated
+/// data.
+#[derive(Debug)]
+pub struct LocalKeyProvider {
+    path: PathBuf,
+    keys: RwLock<KeyFile>,
+}
+
+impl Debug for KeyFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyFile")
+            .field("current", &self.current)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl LocalKeyProvider {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, KeyProviderError> {
+        let path = path.as_ref().to_owned();
+        let keys = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let keys = KeyFile {
+                    current: "key-1".to_owned(),
+                    keys: BTreeMap::from([("key-1".to_owned(), hex(&random_key()))]),
+                };
+                save(&path, &keys)?;
+                keys
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Add a new key and wrap new data keys with it, returning its id
+    ///
+    /// The id is `key-N`, N being one more than the highest N in use.
+    pub fn rotate(&self) -> Result<String, KeyProviderError> {
+        let mut keys = self.keys.write().unwrap();
+        let last = keys
+            .keys
+            .keys()
+            .filter_map(|key_id| key_id.strip_prefix("key-")?.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0);
+        let key_id = format!("key-{}", last + 1);
+        if keys.keys.contains_key(&key_id) {
+            return Err(format!("key {} already exists", key_id).into());
+        }
+        keys.keys.insert(key_id.clone(), hex(&random_key()));
+        let previous = std::mem::replace(&mut keys.current, key_id.clone());
+        if let Err(e) = save(&self.path, &keys) {
+            keys.keys.remove(&key_id);
+            keys.current = previous;
+            return Err(e);
+        }
+        event!(
+            Level::INFO,
+            "Rotated local key encryption key to {}",
+            key_id
+        );
+
+        Ok(key_id)
+    }
+
+    fn cipher(&self, key_id: &str) -> Result<Aes256Gcm, KeyProviderError> {
+        let keys = self.keys.read().unwrap();
+        let key = keys
+            .keys
+            .get(key_id)
+            .ok_or_else(|| format!("unknown key {}", key_id))?;
+
+        Aes256Gcm::new_from_slice(&unhex(key)?)
+            .map_err(|_| format!("key {} isn't 32 bytes long", key_id).into())
+    }
+}
+
+impl KeyProvider for LocalKeyProvider {
+    fn current_key_id(&self) -> String {
+        self.keys.read().unwrap().current.clone()
+    }
+
+    fn wrap_key<'a>(
+        &'a self,
+        data_key: &'a [u8],
+    ) -> BoxFuture<'a, Result<WrappedKey, KeyProviderError>> {
+        async move {
+            let key_id = self.current_key_id();
+            let wrapped = seal(&self.cipher(&key_id)?, data_key, key_id.as_bytes())
+                .ok_or("failed to wrap the data key")?;
+
+            Ok(WrappedKey { key_id, wrapped })
+        }
+        .boxed()
+    }
+
+    fn unwrap_key<'a>(
+        &'a self,
+        key_id: &'a str,
+        wrapped: &'a [u8],
+    ) -> BoxFuture<'a, Result<Vec<u8>, KeyProviderError>> {
+        async move {
+            let data_key = open(&self.cipher(key_id)?, wrapped, key_id.as_bytes())
+                .ok_or_else(|| format!("data key doesn't unwrap with key {}", key_id))?;
+
+            Ok(data_key)
+        }
+        .boxed()
+    }
+}
+
+/// Write `keys` to a temporary file only the owner can read, then move it over `path`
+fn save(path: &Path, keys: &KeyFile) -> Result<(), KeyProviderError> {
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, serde_json::to_vec_pretty(keys)?)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(&partial, std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::fs::rename(&partial, path)?;
+
+    Ok(())
+}
+
+struct DataKey {
+    cipher: Aes256Gcm,
+    wrapped: WrappedKey,
+    created: Instant,
+    uses: u64,
+}
+
+/// Encrypts record payloads with AES-256-GCM data keys wrapped by a KeyProvider
+///
+/// An encrypted payload is a random nonce followed by the ciphertext, authenticated together
+/// with the topic and key id. The record gets a KEY_ID_HEADER and a DATA_KEY_HEADER; keys,
+/// other headers and the topic stay in the clear. Messages consumed from an encrypted topic
+/// must carry a KEY_ID_HEADER.
+pub struct Encryption {
+    provider: Box<dyn KeyProvider>,
+    topics: Option<HashSet<String>>,
+    data_key: Mutex<Option<DataKey>>,
+    unwrapped: Mutex<HashMap<(String, Vec<u8>), Aes256Gcm>>,
+}
+
+impl Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption")
+            .field("provider", &self.provider)
+            .field("topics", &self.topics)
+            .finish()
+    }
+}
+
+impl Encryption {
+    /// Encrypt records for every topic
+    pub fn new(provider: impl KeyProvider) -> Self {
+        Self {
+            provider: Box::new(provider),
+            topics: None,
+            data_key: Mutex::new(None),
+            unwrapped: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Only encrypt records for `topics`
+    pub fn with_topics(mut self, topics: &[&str]) -> Self {
+        self.topics = Some(topics.iter().map(|&topic| topic.to_owned()).collect());
+
+        self
+    }
+
+    pub fn provider(&self) -> &dyn KeyProvider {
+        &*self.provider
+    }
+
+    /// Whether records for `topic` are encrypted
+    pub fn encrypts(&self, topic: &str) -> bool {
+        self.topics
+            .as_ref()
+            .is_none_or(|topics| topics.contains(topic))
+    }
+
+    /// `record` with its payload encrypted, unchanged if its topic isn't encrypted
+    ///
+    /// Fails if the record already has a KEY_ID_HEADER, which can't be told apart from one
+    /// that's already encrypted.
+    pub async fn encrypt<'a>(
+        &self,
+        record: &'a RedpandaRecord,
+    ) -> Result<Cow<'a, RedpandaRecord>, RedpandaError> {
+        if !self.encrypts(record.topic()) {
+            return Ok(Cow::Borrowed(record));
+        }
+        let encrypt_error = |source| RedpandaError::Encrypt {
+            topic: record.topic().to_owned(),
+            source,
+        };
+        if record.headers().contains_key(KEY_ID_HEADER) {
+            return Err(encrypt_error(EncryptionError::HeaderPresent(KEY_ID_HEADER)));
+        }
+        let (cipher, wrapped) = self.data_key().await.map_err(encrypt_error)?;
+        let aad = payload_aad(record.topic(), &wrapped.key_id);
+        let payload = seal(&cipher, record.payload(), &aad).expect("AES-GCM encrypts up to 64 GiB");
+
+        Ok(Cow::Owned(
+            record
+                .clone()
+                .with_payload(payload)
+                .with_header(KEY_ID_HEADER, wrapped.key_id.as_str())
+                .with_header(DATA_KEY_HEADER, &wrapped.wrapped[..]),
+        ))
+    }
+
+    /// `message` with its payload decrypted and the encryption headers removed
+    ///
+    /// Messages without a KEY_ID_HEADER are unchanged if their topic isn't encrypted, and fail
+    /// with InvalidHeader if it is.
+    pub async fn decrypt(&self, message: OwnedMessage) -> Result<OwnedMessage, RedpandaError> {
+        let mut headers = message.header_map();
+        let key_id = match headers.get_str(KEY_ID_HEADER) {
+            None if !self.encrypts(message.topic()) => return Ok(message),
+            None => None,
+            Some(key_id) => key_id.ok(),
+        };
+        let result = self.decrypt_payload(
+            message.topic(),
+            key_id,
+            headers.get(DATA_KEY_HEADER),
+            message.payload(),
+        );
+        let payload = result.await.map_err(|source| RedpandaError::Decrypt {
+            topic: message.topic().to_owned(),
+            partition: message.partition(),
+            offset: message.offset(),
+            source,
+        })?;
+        headers.remove(KEY_ID_HEADER);
+        headers.remove(DATA_KEY_HEADER);
+
+        Ok(OwnedMessage::new(
+            Some(payload),
+            message.key().map(<[u8]>::to_vec),
+            message.topic().to_owned(),
+            message.timestamp(),
+            message.partition(),
+            message.offset(),
+            (!headers.is_empty()).then(|| headers.to_owned_headers()),
+        ))
+    }
+
+    async fn decrypt_payload(
+        &self,
+        topic: &str,
+        key_id: Option<&str>,
+        wrapped: Option<&[u8]>,
+        payload: Option<&[u8]>,
+    ) -> Result<Vec<u8>, EncryptionError> {
+        let key_id = key_id.ok_or(EncryptionError::InvalidHeader(KEY_ID_HEADER))?;
+        let wrapped = wrapped.ok_or(EncryptionError::InvalidHeader(DATA_KEY_HEADER))?;
+        let cache_key = (key_id.to_owned(), wrapped.to_vec());
+        let cached = self.unwrapped.lock().unwrap().get(&cache_key).cloned();
+        let cipher = match cached {
+            Some(cipher) => cipher,
+            None => {
+                let data_key = self
+                    .provider
+                    .unwrap_key(key_id, wrapped)
+                    .await
+                    .map_err(EncryptionError::KeyProvider)?;
+                let cipher = Aes256Gcm::new_from_slice(&data_key).map_err(|_| {
+                    EncryptionError::InvalidDataKey {
+                        key_id: key_id.to_owned(),
+                        len: data_key.len(),
+                    }
+                })?;
+                let mut unwrapped = self.unwrapped.lock().unwrap();
+                if unwrapped.len() >= UNWRAPPED_KEY_CACHE {
+                    unwrapped.clear();
+                }
+                unwrapped.insert(cache_key, cipher.clone());
+                cipher
+            }
+        };
+
+        payload
+            .and_then(|payload| open(&cipher, payload, &payload_aad(topic, key_id)))
+            .ok_or_else(|| EncryptionError::Decryption {
+                key_id: key_id.to_owned(),
+            })
+    }
+
+    /// The current data key, wrapping a new one when it's worn out or the key provider rotated
+    async fn data_key(&self) -> Result<(Aes256Gcm, WrappedKey), EncryptionError> {
+        let current_key_id = self.provider.current_key_id();
+        if let Some(data_key) = self.data_key.lock().unwrap().as_mut().filter(|data_key| {
+            data_key.wrapped.key_id == current_key_id
+                && data_key.created.elapsed() < DATA_KEY_LIFETIME
+                && data_key.uses < DATA_KEY_USES
+        }) {
+            data_key.uses += 1;
+            return Ok((data_key.cipher.clone(), data_key.wrapped.clone()));
+        }
+
+        let key = random_key();
+        let wrapped = self
+            .provider
+            .wrap_key(&key)
+            .await
+            .map_err(EncryptionError::KeyProvider)?;
+        let cipher = Aes256Gcm::new_from_slice(&key).expect("data keys are 32 bytes");
+        event!(
+            Level::DEBUG,
+            "Wrapped a new data key with key {}",
+            wrapped.key_id
+        );
+        *self.data_key.lock().unwrap() = Some(DataKey {
+            cipher: cipher.clone(),
+            wrapped: wrapped.clone(),
+            created: Instant::now(),
+            uses: 1,
+        });
+
+        Ok((cipher, wrapped))
+    }
+}
+
+fn random_key() -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    rand::thread_rng().fill_bytes(&mut key);
+
+    key
+}
+
+/// Associated data binding a payload to its topic and the key that wrapped its data key
+fn payload_aad(topic: &str, key_id: &str) -> Vec<u8> {
+    [topic.as_bytes(), &[0], key_id.as_bytes()].concat()
+}
+
+/// A random nonce followed by `plaintext` encrypted with `cipher` and authenticated with `aad`
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), payload).ok()?;
+
+    Some([&nonce[..], &ciphertext].concat())
+}
+
+/// Decrypt what `seal` produced, None if it's been tampered with, used another key or
+/// another `aad`
+fn open(cipher: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+        .ok()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(hex: &str) -> Result<Vec<u8>, KeyProviderError> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            Ok(u8::from_str_radix(
+                hex.get(i..i + 2).ok_or("odd number of hex digits")?,
+                16,
+            )?)
+        })
+        .collect()
+}
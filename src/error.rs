@@ -1,16 +1,201 @@
 use std::array::TryFromSliceError;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub use rdkafka::error::*;
+use rdkafka::types::RDKafkaConfRes;
 use thiserror::Error;
 
+use crate::claim_check::ObjectStoreError;
+use crate::codec::{CodecError, RecordPart};
+use crate::config::CompressionType;
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionError;
+use crate::interceptor::InterceptorError;
+use crate::role::ClientRole;
+use crate::spool::SpoolError;
+
 #[derive(Error, Debug)]
 pub enum RedpandaError {
     #[error("Redpanda encountered a Kafka error")]
-    Kafka(#[from] rdkafka::error::KafkaError),
+    Kafka(#[source] rdkafka::error::KafkaError),
+    #[error("invalid configuration for key `{key}`: {message}")]
+    ClientConfig { key: String, message: String },
+    #[error("librdkafka rejected the client configuration: {0}")]
+    ClientCreation(String),
+    /// A builder setting only the async clients apply was set for a blocking client
+    #[error("{setting} is not supported by blocking {role:?} clients")]
+    AsyncOnly {
+        setting: &'static str,
+        role: ClientRole,
+    },
+    #[error("invalid setting `{key}` in {origin}: {message}")]
+    Config {
+        origin: String,
+        key: String,
+        message: String,
+    },
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("failed to read config file {}", path.display())]
+    ConfigFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "topic {topic} recompresses the producer's {producer} batches with {topic_compression}"
+    )]
+    CompressionMismatch {
+        topic: String,
+        producer: CompressionType,
+        topic_compression: CompressionType,
+    },
+    #[error("a blocking task panicked or was cancelled")]
+    Task(#[from] tokio::task::JoinError),
+    #[error("{kind} transaction error")]
+    Transaction {
+        kind: TransactionErrorKind,
+        #[source]
+        error: KafkaError,
+    },
+    #[error("failed to serialize the {part} of a record for topic {topic}")]
+    Serialize {
+        topic: String,
+        part: RecordPart,
+        source: CodecError,
+    },
+    #[error("failed to deserialize the {part} of the message at {topic}/{partition}@{offset}")]
+    Deserialize {
+        topic: String,
+        partition: i32,
+        offset: i64,
+        part: RecordPart,
+        source: CodecError,
+    },
+    #[error("claim check object {key} couldn't be stored or fetched")]
+    ClaimCheck {
+        key: String,
+        source: ObjectStoreError,
+    },
+    #[cfg(feature = "encryption")]
+    #[error("failed to encrypt the payload of a record for topic {topic}")]
+    Encrypt {
+        topic: String,
+        source: EncryptionError,
+    },
+    #[cfg(feature = "encryption")]
+    #[error("failed to decrypt the payload of the message at {topic}/{partition}@{offset}")]
+    Decrypt {
+        topic: String,
+        partition: i32,
+        offset: i64,
+        source: EncryptionError,
+    },
+    #[error("interceptor {interceptor} rejected a record for topic {topic}")]
+    Rejected {
+        interceptor: String,
+        topic: String,
+        source: InterceptorError,
+    },
+    #[error("interceptor {interceptor} rejected the message at {topic}/{partition}@{offset}")]
+    RejectedMessage {
+        interceptor: String,
+        topic: String,
+        partition: i32,
+        offset: i64,
+        source: InterceptorError,
+    },
+    #[error(
+        "circuit breaker is open after {failures} consecutive delivery failures, retry in \
+         {retry_in:?}"
+    )]
+    CircuitOpen { failures: u32, retry_in: Duration },
+    #[error("spool error")]
+    Spool(#[from] SpoolError),
     #[error("unknown Redpanda error")]
     Unknown,
 }
 
+impl RedpandaError {
+    /// Returns the librdkafka error code behind this error, if any
+    pub fn rdkafka_error_code(&self) -> Option<RDKafkaErrorCode> {
+        match self {
+            RedpandaError::Kafka(e) => e.rdkafka_error_code(),
+            RedpandaError::Transaction { error, .. } => error.rdkafka_error_code(),
+            _ => None,
+        }
+    }
+
+    /// How to recover from a failed transactional operation, None for other errors
+    pub fn transaction_error_kind(&self) -> Option<TransactionErrorKind> {
+        match self {
+            RedpandaError::Transaction { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+}
+
+/// What a transactional producer can do after an operation fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionErrorKind {
+    /// The operation timed out or hit a transient error and can be called again
+    Retriable,
+    /// The current transaction must be aborted, after which a new one can begin
+    Abortable,
+    /// The producer is unusable, e.g. it was fenced by a newer producer with the same
+    /// transactional.id, and must be dropped and rebuilt
+    Fatal,
+}
+
+impl Display for TransactionErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransactionErrorKind::Retriable => write!(f, "retriable"),
+            TransactionErrorKind::Abortable => write!(f, "abortable"),
+            TransactionErrorKind::Fatal => write!(f, "fatal"),
+        }
+    }
+}
+
+/// Classify an error from a transactional producer operation
+///
+/// Errors librdkafka doesn't flag as fatal, abortable or retriable are API misuse, e.g.
+/// committing without beginning a transaction, and stay plain Kafka errors
+pub(crate) fn transaction_error(error: KafkaError) -> RedpandaError {
+    let kind = match &error {
+        KafkaError::Transaction(e) if e.is_fatal() => TransactionErrorKind::Fatal,
+        KafkaError::Transaction(e) if e.txn_requires_abort() => TransactionErrorKind::Abortable,
+        KafkaError::Transaction(e) if e.is_retriable() => TransactionErrorKind::Retriable,
+        _ => return RedpandaError::from(error),
+    };
+
+    RedpandaError::Transaction { kind, error }
+}
+
+/// Config errors are pulled out of KafkaError so callers can match on the offending key
+impl From<KafkaError> for RedpandaError {
+    fn from(e: KafkaError) -> Self {
+        match e {
+            KafkaError::ClientConfig(_, message, key, _) => {
+                RedpandaError::ClientConfig { key, message }
+            }
+            KafkaError::ClientCreation(message) => RedpandaError::ClientCreation(message),
+            e => RedpandaError::Kafka(e),
+        }
+    }
+}
+
+/// Build the KafkaError librdkafka itself returns for an invalid config value
+pub(crate) fn invalid_config(key: &str, value: &str, description: &str) -> KafkaError {
+    KafkaError::ClientConfig(
+        RDKafkaConfRes::RD_KAFKA_CONF_INVALID,
+        description.to_owned(),
+        key.to_owned(),
+        value.to_owned(),
+    )
+}
+
 #[derive(Error, Debug)]
 pub enum RecordError {
     #[error("key doesn't deserialize to a DateTime<Utc>")]
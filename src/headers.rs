@@ -0,0 +1,130 @@
+use std::str::Utf8Error;
+
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders};
+
+use crate::codec::{CodecError, Deserializer};
+
+/// Headers of a record or consumed message, in order
+///
+/// Kafka allows repeated keys; `get` and friends return the last value for a key, like the
+/// Java client's `lastHeader`, and `get_all` returns every value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    headers: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy headers out of librdkafka's representation
+    pub fn from_headers<H: Headers + ?Sized>(headers: &H) -> Self {
+        let headers = (0..headers.count())
+            .filter_map(|i| headers.try_get(i))
+            .map(|h| (h.key.to_owned(), h.value.map(<[u8]>::to_vec)))
+            .collect();
+
+        Self { headers }
+    }
+
+    /// Append a header; a `None` value is a null header
+    pub fn append(&mut self, key: &str, value: Option<&[u8]>) -> &mut Self {
+        self.headers
+            .push((key.to_owned(), value.map(<[u8]>::to_vec)));
+
+        self
+    }
+
+    /// Remove every header named `key`, returning how many there were
+    pub fn remove(&mut self, key: &str) -> usize {
+        let len = self.headers.len();
+        self.headers.retain(|(k, _)| k != key);
+
+        len - self.headers.len()
+    }
+
+    /// The last value for `key`, None if there's no such header or its value is null
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+
+    /// The last value for `key` as UTF-8
+    pub fn get_str(&self, key: &str) -> Option<Result<&str, Utf8Error>> {
+        self.get(key).map(std::str::from_utf8)
+    }
+
+    /// The last value for `key` decoded with `deserializer`
+    pub fn get_as<T>(
+        &self,
+        key: &str,
+        deserializer: &impl Deserializer<T>,
+    ) -> Option<Result<T, CodecError>> {
+        self.get(key).map(|value| deserializer.deserialize(value))
+    }
+
+    /// Every value for `key`, in order, including nulls
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = Option<&'a [u8]>> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_deref())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.headers.iter().any(|(k, _)| k == key)
+    }
+
+    /// Keys in order, repeated keys included
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.headers.iter().map(|(k, _)| k.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&[u8]>)> {
+        self.headers.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+
+    /// Number of headers, repeated keys included
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// librdkafka's representation, for producing
+    pub fn to_owned_headers(&self) -> OwnedHeaders {
+        self.headers.iter().fold(
+            OwnedHeaders::new_with_capacity(self.headers.len()),
+            |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: value.as_deref(),
+                })
+            },
+        )
+    }
+}
+
+impl From<&OwnedHeaders> for HeaderMap {
+    fn from(headers: &OwnedHeaders) -> Self {
+        Self::from_headers(headers)
+    }
+}
+
+/// View the headers of consumed messages as a HeaderMap
+pub trait MessageHeaders: Message {
+    /// The message's headers, empty if it has none
+    fn header_map(&self) -> HeaderMap {
+        self.headers()
+            .map(HeaderMap::from_headers)
+            .unwrap_or_default()
+    }
+}
+
+impl<M: Message + ?Sized> MessageHeaders for M {}
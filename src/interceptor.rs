@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use rdkafka::message::{Message, OwnedMessage};
+
+use crate::delivery::DeliveryEvent;
+use crate::error::RedpandaError;
+use crate::producer::RedpandaRecord;
+
+/// Error an Interceptor rejects a record or message with
+pub type InterceptorError = Box<dyn Error + Send + Sync>;
+
+/// Interceptors registered on a RedpandaBuilder, in the order they run
+pub(crate) type Interceptors = Arc<[Arc<dyn Interceptor>]>;
+
+/// Cross-cutting hooks run by the producers and consumers a RedpandaBuilder builds, e.g. to
+/// stamp headers, audit, validate payloads or count records
+///
+/// Interceptors run in the order they were added, each one getting what the previous one
+/// returned. Every hook has a default that passes records through. The `_raw` methods and
+/// records sent straight through the inner rdkafka producer bypass them.
+pub trait Interceptor: Send + Sync + 'static {
+    /// Name used in errors and logs
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// A record is about to be sent by any of the producer's send methods or a RedpandaSink,
+    /// before it's encrypted or checked in
+    ///
+    /// Return the record, changed or not, or an error to reject it, which fails the send with
+    /// RedpandaError::Rejected.
+    fn on_send(&self, record: RedpandaRecord) -> Result<RedpandaRecord, InterceptorError> {
+        Ok(record)
+    }
+
+    /// A record `on_send` saw was delivered or failed for good
+    ///
+    /// Called on the producer's polling thread, so it must not block. Records sent with
+    /// `PendingDelivery::new` are skipped, as they didn't go through `on_send`.
+    fn on_ack(&self, _event: &DeliveryEvent) {}
+
+    /// A message was received by `recv_processed`, `recv_typed` or `stream_processed`, after it
+    /// was checked out and decrypted
+    ///
+    /// Return the message, changed or not, or an error to reject it, which fails the receive
+    /// with RedpandaError::RejectedMessage. The message is consumed either way.
+    fn on_consume(&self, message: OwnedMessage) -> Result<OwnedMessage, InterceptorError> {
+        Ok(message)
+    }
+}
+
+impl<T: Interceptor + ?Sized> Interceptor for Arc<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn on_send(&self, record: RedpandaRecord) -> Result<RedpandaRecord, InterceptorError> {
+        (**self).on_send(record)
+    }
+
+    fn on_ack(&self, event: &DeliveryEvent) {
+        (**self).on_ack(event)
+    }
+
+    fn on_consume(&self, message: OwnedMessage) -> Result<OwnedMessage, InterceptorError> {
+        (**self).on_consume(message)
+    }
+}
+
+/// Run `record` through every interceptor's `on_send`
+pub(crate) fn on_send(
+    interceptors: &[Arc<dyn Interceptor>],
+    mut record: RedpandaRecord,
+) -> Result<RedpandaRecord, RedpandaError> {
+    for interceptor in interceptors {
+        let topic = record.topic().to_owned();
+        record = interceptor
+            .on_send(record)
+            .map_err(|source| RedpandaError::Rejected {
+                interceptor: interceptor.name().to_owned(),
+                topic,
+                source,
+            })?;
+    }
+
+    Ok(record)
+}
+
+/// Run `message` through every interceptor's `on_consume`
+pub(crate) fn on_consume(
+    interceptors: &[Arc<dyn Interceptor>],
+    mut message: OwnedMessage,
+) -> Result<OwnedMessage, RedpandaError> {
+    for interceptor in interceptors {
+        let (topic, partition, offset) = (
+            message.topic().to_owned(),
+            message.partition(),
+            message.offset(),
+        );
+        message =
+            interceptor
+                .on_consume(message)
+                .map_err(|source| RedpandaError::RejectedMessage {
+                    interceptor: interceptor.name().to_owned(),
+                    topic,
+                    partition,
+                    offset,
+                    source,
+                })?;
+    }
+
+    Ok(message)
+}
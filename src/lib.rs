@@ -1,10 +1,31 @@
 pub mod admin;
+pub mod blocking;
 pub mod builder;
+pub mod claim_check;
+pub mod codec;
 pub mod config;
 pub mod consumer;
+pub mod context;
+pub mod delivery;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+pub mod headers;
+pub mod interceptor;
 pub mod metadata;
+pub mod mock;
+pub mod oauth;
+pub mod partitioner;
 pub mod producer;
+pub mod profile;
+pub mod role;
+#[cfg(feature = "schema-registry")]
+pub mod schema_registry;
+pub mod security;
+pub mod sink;
+pub mod spool;
+pub mod throttle;
+mod transaction;
 
 #[cfg(test)]
 mod tests;
@@ -12,10 +33,12 @@ mod tests;
 pub use admin::RedpandaAdminClient;
 pub use builder::RedpandaBuilder;
 pub use consumer::RedpandaConsumer;
+pub use context::RedpandaContext;
 pub use producer::RedpandaProducer;
 pub use rdkafka::groups;
 pub use rdkafka::message;
 pub use rdkafka::statistics;
+pub use sink::RedpandaSink;
 
 pub mod types {
     pub use rdkafka::types::*;
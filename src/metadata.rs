@@ -1,8 +1,15 @@
 use rdkafka::{
+    client::Client,
+    error::KafkaError,
     metadata::{Metadata, MetadataBroker, MetadataPartition, MetadataTopic},
     types::RDKafkaRespErr,
+    util::Timeout,
+    ClientContext,
 };
 use std::convert::From;
+use tracing::{event, Level};
+
+use crate::role::ClientRole;
 
 #[derive(Debug)]
 pub struct RedpandaMetadata {
@@ -27,6 +34,54 @@ impl RedpandaMetadata {
 
         topic_names
     }
+
+    /// Number of partitions of `topic`, or `None` if it isn't in the metadata or has an error
+    pub fn partition_count(&self, topic: &str) -> Option<i32> {
+        self.topics
+            .iter()
+            .find(|t| t.name == topic && t.error.is_none())
+            .and_then(|t| t.partitions.len().try_into().ok())
+            .filter(|&count| count > 0)
+    }
+}
+
+/// Check that the brokers respond to a new client within `timeout` by fetching the cluster's
+/// metadata
+pub(crate) fn check_connection<C: ClientContext>(
+    client: &Client<C>,
+    role: ClientRole,
+    timeout: Timeout,
+) -> Result<RedpandaMetadata, KafkaError> {
+    let metadata: RedpandaMetadata = client.fetch_metadata(None, timeout)?.into();
+    event!(
+        Level::INFO,
+        "Connected {:?} to Redpanda cluster {:?}",
+        role,
+        metadata
+    );
+
+    Ok(metadata)
+}
+
+/// Check that every topic in `topic_names` exists before subscribing to it
+pub(crate) fn check_subscription(
+    metadata: &RedpandaMetadata,
+    topic_names: &[&str],
+) -> Result<(), KafkaError> {
+    let cluster_topic_names = metadata.topic_names();
+    for topic in topic_names {
+        if cluster_topic_names
+            .binary_search(&(*topic).to_owned())
+            .is_err()
+        {
+            return Err(KafkaError::Subscription(format!(
+                "Invalid topic name {}",
+                topic
+            )));
+        }
+    }
+
+    Ok(())
 }
 
 impl From<Metadata> for RedpandaMetadata {
@@ -0,0 +1,235 @@
+use std::ffi::{CStr, CString};
+use std::fmt::Debug;
+use std::os::raw::{c_char, c_int};
+use std::time::Duration;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{BaseProducer, Producer};
+use rdkafka::types::{RDKafka, RDKafkaErrorCode, RDKafkaRespErr};
+use tracing::{event, instrument, Level};
+
+/// Opaque librdkafka rd_kafka_mock_cluster_t
+#[repr(C)]
+struct NativeMockCluster {
+    _private: [u8; 0],
+}
+
+// rdkafka-sys doesn't generate bindings for rdkafka_mock.h, but the symbols are part of the
+// librdkafka it links. Error codes are passed as c_int, which is how C passes enums.
+extern "C" {
+    fn rd_kafka_handle_mock_cluster(rk: *const RDKafka) -> *mut NativeMockCluster;
+    fn rd_kafka_mock_cluster_bootstraps(mcluster: *const NativeMockCluster) -> *const c_char;
+    fn rd_kafka_mock_topic_create(
+        mcluster: *mut NativeMockCluster,
+        topic: *const c_char,
+        partition_cnt: c_int,
+        replication_factor: c_int,
+    ) -> RDKafkaRespErr;
+    fn rd_kafka_mock_topic_set_error(
+        mcluster: *mut NativeMockCluster,
+        topic: *const c_char,
+        err: c_int,
+    );
+    fn rd_kafka_mock_push_request_errors_array(
+        mcluster: *mut NativeMockCluster,
+        api_key: i16,
+        cnt: usize,
+        errors: *const c_int,
+    );
+    fn rd_kafka_mock_broker_set_rtt(
+        mcluster: *mut NativeMockCluster,
+        broker_id: i32,
+        rtt_ms: c_int,
+    ) -> RDKafkaRespErr;
+    fn rd_kafka_mock_broker_set_down(
+        mcluster: *mut NativeMockCluster,
+        broker_id: i32,
+    ) -> RDKafkaRespErr;
+    fn rd_kafka_mock_broker_set_up(
+        mcluster: *mut NativeMockCluster,
+        broker_id: i32,
+    ) -> RDKafkaRespErr;
+}
+
+/// Kafka protocol requests the mock brokers can be told to fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KafkaApi {
+    Produce = 0,
+    Fetch = 1,
+    ListOffsets = 2,
+    Metadata = 3,
+    OffsetCommit = 8,
+    OffsetFetch = 9,
+    FindCoordinator = 10,
+    JoinGroup = 11,
+    Heartbeat = 12,
+    LeaveGroup = 13,
+    SyncGroup = 14,
+    ApiVersions = 18,
+    InitProducerId = 22,
+    AddPartitionsToTxn = 24,
+    AddOffsetsToTxn = 25,
+    EndTxn = 26,
+    TxnOffsetCommit = 28,
+}
+
+/// In-process cluster of librdkafka mock brokers
+///
+/// The cluster is owned by an idle client created with `test.mock.num.brokers` and is torn down
+/// when this struct is dropped, so keep it (or the RedpandaBuilder holding it) alive for as long
+/// as clients connected to it are in use.
+///
+/// The mock brokers support producing, consuming, consumer groups and transactions, but not admin
+/// requests; RedpandaAdminClients built against a mock cluster create topics through it instead.
+pub struct RedpandaMockCluster {
+    // Owns the mock cluster; must outlive `cluster`
+    _handle: BaseProducer,
+    cluster: *mut NativeMockCluster,
+    num_brokers: u16,
+    bootstrap_servers: String,
+}
+
+// The mock API only enqueues operations for the mock cluster's own thread
+unsafe impl Send for RedpandaMockCluster {}
+unsafe impl Sync for RedpandaMockCluster {}
+
+impl RedpandaMockCluster {
+    /// Start a mock cluster with broker ids 1 to `num_brokers`
+    #[instrument]
+    pub fn new(num_brokers: u16) -> Result<Self, KafkaError> {
+        let handle: BaseProducer = ClientConfig::new()
+            .set("test.mock.num.brokers", num_brokers.to_string())
+            .create()?;
+
+        let cluster = unsafe { rd_kafka_handle_mock_cluster(handle.client().native_ptr()) };
+        if cluster.is_null() {
+            return Err(KafkaError::ClientCreation(
+                "librdkafka did not create a mock cluster".to_owned(),
+            ));
+        }
+        let bootstrap_servers =
+            unsafe { CStr::from_ptr(rd_kafka_mock_cluster_bootstraps(cluster)) }
+                .to_string_lossy()
+                .into_owned();
+        event!(
+            Level::INFO,
+            "Started mock cluster with {} brokers at {}",
+            num_brokers,
+            bootstrap_servers
+        );
+
+        Ok(Self {
+            _handle: handle,
+            cluster,
+            num_brokers,
+            bootstrap_servers,
+        })
+    }
+
+    /// The mock brokers' addresses, `host:port,host:port`
+    pub fn bootstrap_servers(&self) -> &str {
+        &self.bootstrap_servers
+    }
+
+    /// Number of brokers in the cluster
+    pub fn num_brokers(&self) -> u16 {
+        self.num_brokers
+    }
+
+    /// Create a topic, failing like a real cluster would on invalid parameters
+    pub fn create_topic(
+        &self,
+        name: &str,
+        num_partitions: u16,
+        replication_factor: u16,
+    ) -> Result<(), KafkaError> {
+        // The mock cluster asserts on these instead of returning an error
+        if num_partitions == 0 {
+            return Err(KafkaError::AdminOp(RDKafkaErrorCode::InvalidPartitions));
+        }
+        if replication_factor == 0 || replication_factor > self.num_brokers {
+            return Err(KafkaError::AdminOp(
+                RDKafkaErrorCode::InvalidReplicationFactor,
+            ));
+        }
+
+        let name = CString::new(name)?;
+        let err = unsafe {
+            rd_kafka_mock_topic_create(
+                self.cluster,
+                name.as_ptr(),
+                num_partitions.into(),
+                replication_factor.into(),
+            )
+        };
+        check(err, KafkaError::AdminOp)
+    }
+
+    /// Make Metadata (and AddPartitionsToTxn) requests report `error` for `topic`
+    pub fn set_topic_error(&self, topic: &str, error: RDKafkaErrorCode) -> Result<(), KafkaError> {
+        let topic = CString::new(topic)?;
+        unsafe { rd_kafka_mock_topic_set_error(self.cluster, topic.as_ptr(), error as c_int) };
+
+        Ok(())
+    }
+
+    /// Fail the next `errors.len()` requests of type `api`, in order, with the given errors
+    ///
+    /// `RDKafkaErrorCode::BrokerTransportFailure` makes the broker disconnect the client instead
+    pub fn push_request_errors(&self, api: KafkaApi, errors: &[RDKafkaErrorCode]) {
+        let errors: Vec<c_int> = errors.iter().map(|e| *e as c_int).collect();
+        unsafe {
+            rd_kafka_mock_push_request_errors_array(
+                self.cluster,
+                api as i16,
+                errors.len(),
+                errors.as_ptr(),
+            )
+        };
+    }
+
+    /// Delay every response from broker `broker_id` by `rtt`
+    ///
+    /// broker_id: -1 for all brokers
+    pub fn set_broker_rtt(&self, broker_id: i32, rtt: Duration) -> Result<(), KafkaError> {
+        let rtt_ms = rtt.as_millis().try_into().unwrap_or(c_int::MAX);
+        let err = unsafe { rd_kafka_mock_broker_set_rtt(self.cluster, broker_id, rtt_ms) };
+        check(err, KafkaError::Global)
+    }
+
+    /// Disconnect clients from broker `broker_id` and refuse new connections
+    ///
+    /// broker_id: -1 for all brokers
+    pub fn set_broker_down(&self, broker_id: i32) -> Result<(), KafkaError> {
+        let err = unsafe { rd_kafka_mock_broker_set_down(self.cluster, broker_id) };
+        check(err, KafkaError::Global)
+    }
+
+    /// Accept connections on broker `broker_id` again
+    ///
+    /// broker_id: -1 for all brokers
+    pub fn set_broker_up(&self, broker_id: i32) -> Result<(), KafkaError> {
+        let err = unsafe { rd_kafka_mock_broker_set_up(self.cluster, broker_id) };
+        check(err, KafkaError::Global)
+    }
+}
+
+impl Debug for RedpandaMockCluster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedpandaMockCluster")
+            .field("num_brokers", &self.num_brokers)
+            .field("bootstrap_servers", &self.bootstrap_servers)
+            .finish()
+    }
+}
+
+fn check(
+    err: RDKafkaRespErr,
+    into_error: fn(RDKafkaErrorCode) -> KafkaError,
+) -> Result<(), KafkaError> {
+    match RDKafkaErrorCode::from(err) {
+        RDKafkaErrorCode::NoError => Ok(()),
+        code => Err(into_error(code)),
+    }
+}
@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::BoxFuture;
+use rdkafka::client::{Client, OAuthToken};
+use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::consumer::Rebalance;
+use rdkafka::error::KafkaError;
+use rdkafka::producer::DeliveryResult;
+use rdkafka::statistics::Statistics;
+use rdkafka::ClientContext;
+use rdkafka_sys::{
+    rd_kafka_error_destroy, rd_kafka_error_string, rd_kafka_sasl_background_callbacks_enable,
+};
+use tracing::{event, Level};
+
+use crate::config::EffectiveConfig;
+use crate::context::{RedpandaContext, TracingContext};
+use crate::security::REDACTED;
+
+/// Error returned by a TokenProvider
+pub type TokenError = Box<dyn Error + Send + Sync>;
+
+/// A SASL/OAUTHBEARER token and the Kafka principal it authenticates as
+#[derive(Clone, PartialEq, Eq)]
+pub struct OAuthBearerToken {
+    pub token: String,
+    pub principal: String,
+    /// librdkafka fetches a new token when 80% of the time until expiry has passed
+    pub expires_at: SystemTime,
+}
+
+impl Debug for OAuthBearerToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthBearerToken")
+            .field("token", &REDACTED)
+            .field("principal", &self.principal)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// Source of OAUTHBEARER tokens, e.g. an OIDC client credentials flow
+///
+/// Called when a client is created and again before each token expires
+pub trait TokenProvider: Send + Sync + 'static {
+    fn fetch_token(&self) -> BoxFuture<'_, Result<OAuthBearerToken, TokenError>>;
+}
+
+impl<T: TokenProvider + ?Sized> TokenProvider for Arc<T> {
+    fn fetch_token(&self) -> BoxFuture<'_, Result<OAuthBearerToken, TokenError>> {
+        (**self).fetch_token()
+    }
+}
+
+/// Hands out the same token with a fresh expiry on every fetch, for tests and local clusters
+#[derive(Debug)]
+pub struct StaticTokenProvider {
+    token: String,
+    principal: String,
+    lifetime: Duration,
+    fetches: AtomicUsize,
+}
+
+impl StaticTokenProvider {
+    pub fn new(token: &str, principal: &str, lifetime: Duration) -> Self {
+        Self {
+            token: token.to_owned(),
+            principal: principal.to_owned(),
+            lifetime,
+            fetches: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of tokens handed out so far
+    pub fn fetch_count(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+}
+
+impl TokenProvider for StaticTokenProvider {
+    fn fetch_token(&self) -> BoxFuture<'_, Result<OAuthBearerToken, TokenError>> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        let token = OAuthBearerToken {
+            token: self.token.clone(),
+            principal: self.principal.clone(),
+            expires_at: SystemTime::now() + self.lifetime,
+        };
+
+        Box::pin(async move { Ok(token) })
+    }
+}
+
+/// RedpandaContext that gets OAUTHBEARER tokens from a TokenProvider and forwards every other
+/// callback to the wrapped context
+///
+/// Made by RedpandaBuilder::with_token_provider. Tokens are fetched on librdkafka's background
+/// thread, blocking it until `fetch_token` completes. Each fetch runs on a thread and
+/// current_thread tokio runtime of its own, so providers can use tokio I/O whatever runtime, if
+/// any, the client is used from, but not I/O objects bound to another runtime.
+pub struct OAuthContext<C: RedpandaContext = TracingContext> {
+    inner: Arc<C>,
+    provider: Arc<dyn TokenProvider>,
+}
+
+impl<C: RedpandaContext> OAuthContext<C> {
+    pub(crate) fn new(inner: Arc<C>, provider: Arc<dyn TokenProvider>) -> Self {
+        Self { inner, provider }
+    }
+
+    /// The context every callback but token refresh is forwarded to
+    pub fn inner(&self) -> &Arc<C> {
+        &self.inner
+    }
+
+    pub fn token_provider(&self) -> &Arc<dyn TokenProvider> {
+        &self.provider
+    }
+
+    /// Run the provider's fetch to completion on a fresh runtime
+    ///
+    /// Not on the client's runtime: a current_thread one may be blocked waiting for librdkafka,
+    /// e.g. while a client is created, and block_on panics if called from a runtime's thread
+    fn fetch_token(&self) -> Result<OAuthBearerToken, TokenError> {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?
+                        .block_on(self.provider.fetch_token())
+                })
+                .join()
+                .unwrap_or_else(|_| Err("token provider panicked".into()))
+        })
+    }
+}
+
+impl<C: RedpandaContext> RedpandaContext for OAuthContext<C> {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+    fn delivery(&self, delivery_result: &DeliveryResult<'_>) {
+        self.inner.delivery(delivery_result);
+    }
+
+    fn error(&self, error: KafkaError, reason: &str) {
+        self.inner.error(error, reason);
+    }
+
+    fn stats(&self, statistics: Statistics) {
+        self.inner.stats(statistics);
+    }
+
+    fn log(&self, level: RDKafkaLogLevel, fac: &str, log_message: &str) {
+        self.inner.log(level, fac, log_message);
+    }
+
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.inner.pre_rebalance(rebalance);
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        self.inner.post_rebalance(rebalance);
+    }
+
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        let token = self.fetch_token().map_err(|e| {
+            event!(Level::ERROR, "Failed to fetch OAUTHBEARER token: {}", e);
+            e.to_string()
+        })?;
+        let lifetime_ms = token
+            .expires_at
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| "token expiry is before the Unix epoch")?
+            .as_millis()
+            .try_into()?;
+        event!(
+            Level::DEBUG,
+            "Fetched OAUTHBEARER token for {} expiring at {:?}",
+            token.principal,
+            token.expires_at
+        );
+
+        Ok(OAuthToken {
+            token: token.token,
+            principal_name: token.principal,
+            lifetime_ms,
+        })
+    }
+}
+
+/// Whether clients built from `config` get OAUTHBEARER tokens from the context
+pub(crate) fn uses_token_refresh<C: RedpandaContext>(config: &EffectiveConfig) -> bool {
+    let mechanism = config
+        .get("sasl.mechanism")
+        .or_else(|| config.get("sasl.mechanisms"));
+
+    C::ENABLE_REFRESH_OAUTH_TOKEN
+        && mechanism.is_some_and(|m| m.eq_ignore_ascii_case("OAUTHBEARER"))
+}
+
+/// Serve token refreshes on librdkafka's background thread instead of the client's main queue,
+/// which admin clients never poll and consumers only poll while receiving messages
+///
+/// The client must have been created with `enable_sasl_queue=true`
+pub(crate) fn enable_background_refresh<C: ClientContext>(
+    client: &Client<C>,
+) -> Result<(), KafkaError> {
+    let error = unsafe { rd_kafka_sasl_background_callbacks_enable(client.native_ptr()) };
+    if error.is_null() {
+        return Ok(());
+    }
+    let message = unsafe { CStr::from_ptr(rd_kafka_error_string(error)) }
+        .to_string_lossy()
+        .into_owned();
+    unsafe { rd_kafka_error_destroy(error) };
+
+    Err(KafkaError::ClientCreation(message))
+}
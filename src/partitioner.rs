@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rand::Rng;
+
+/// Picks the partition for records that don't set one with RedpandaRecord::with_partition
+///
+/// Set with RedpandaBuilder::set_partitioner. Without one, librdkafka's `partitioner` property
+/// decides (consistent_random by default).
+pub trait Partitioner: Debug + Send + Sync + 'static {
+    /// A partition in `0..partition_count` for a record with `key` sent to `topic`
+    ///
+    /// `partition_count` is always at least 1
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32;
+}
+
+impl<T: Partitioner + ?Sized> Partitioner for Arc<T> {
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32 {
+        (**self).partition(topic, key, partition_count)
+    }
+}
+
+/// Java client's murmur2 hash, as used by its DefaultPartitioner
+pub fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let tail = chunks.remainder();
+    if tail.len() == 3 {
+        h ^= (tail[2] as u32) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= (tail[1] as u32) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= tail[0] as u32;
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+
+    h as i32
+}
+
+/// Partition for `key` matching the Java client's DefaultPartitioner
+fn murmur2_partition(key: &[u8], partition_count: i32) -> i32 {
+    (murmur2(key) & 0x7fff_ffff) % partition_count
+}
+
+fn random_partition(partition_count: i32) -> i32 {
+    rand::thread_rng().gen_range(0..partition_count)
+}
+
+/// Keyed records go to the same partition as they would from a Java producer; keyless records
+/// to a random partition, like librdkafka's murmur2_random
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Murmur2Partitioner;
+
+impl Partitioner for Murmur2Partitioner {
+    fn partition(&self, _topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32 {
+        match key {
+            Some(key) => murmur2_partition(key, partition_count),
+            None => random_partition(partition_count),
+        }
+    }
+}
+
+/// Keyed records go to the partition picked by the CRC32 of the key; keyless records to a
+/// random partition, like librdkafka's consistent_random
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsistentRandomPartitioner;
+
+impl Partitioner for ConsistentRandomPartitioner {
+    fn partition(&self, _topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32 {
+        match key {
+            Some(key) => (crc32fast::hash(key) % partition_count as u32) as i32,
+            None => random_partition(partition_count),
+        }
+    }
+}
+
+/// Cycles through each topic's partitions, ignoring keys
+#[derive(Debug, Default)]
+pub struct RoundRobinPartitioner {
+    next: Mutex<HashMap<String, Arc<AtomicU32>>>,
+}
+
+impl Partitioner for RoundRobinPartitioner {
+    fn partition(&self, topic: &str, _key: Option<&[u8]>, partition_count: i32) -> i32 {
+        let counter = self
+            .next
+            .lock()
+            .unwrap()
+            .entry(topic.to_owned())
+            .or_default()
+            .clone();
+
+        (counter.fetch_add(1, Ordering::Relaxed) % partition_count as u32) as i32
+    }
+}
+
+/// Keyed records are partitioned like Murmur2Partitioner; keyless records stick to one random
+/// partition per topic, moving to another after `records_per_partition` records, so they fill
+/// bigger batches than with random or round-robin partitioning
+#[derive(Debug)]
+pub struct StickyPartitioner {
+    records_per_partition: u32,
+    sticky: Mutex<HashMap<String, (i32, u32)>>,
+}
+
+impl StickyPartitioner {
+    pub fn new(records_per_partition: u32) -> Self {
+        Self {
+            records_per_partition: records_per_partition.max(1),
+            sticky: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Default for StickyPartitioner {
+    /// Switches partitions every 100 records
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl Partitioner for StickyPartitioner {
+    fn partition(&self, topic: &str, key: Option<&[u8]>, partition_count: i32) -> i32 {
+        if let Some(key) = key {
+            return murmur2_partition(key, partition_count);
+        }
+        let mut sticky = self.sticky.lock().unwrap();
+        let (partition, sent) = sticky
+            .entry(topic.to_owned())
+            .or_insert_with(|| (random_partition(partition_count), 0));
+        if *sent >= self.records_per_partition || *partition >= partition_count {
+            // Move to a different partition when there is one
+            let previous = *partition;
+            *partition = random_partition(partition_count);
+            if *partition == previous && partition_count > 1 {
+                *partition = (previous + 1) % partition_count;
+            }
+            *sent = 0;
+        }
+        *sent += 1;
+
+        *partition
+    }
+}
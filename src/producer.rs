@@ -1,21 +1,100 @@
-use crate::{builder::TracingProducerContext, metadata::RedpandaMetadata};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::Encryption;
+use crate::{
+    claim_check::ClaimCheck,
+    codec::{RecordPart, Serializer},
+    config::EffectiveConfig,
+    context::{ContextAdapter, RedpandaContext, TracingContext},
+    delivery::{DeliveryEvent, DeliveryStats, PendingDelivery},
+    error::RedpandaError,
+    headers::HeaderMap,
+    interceptor::{self, Interceptors},
+    metadata::{check_connection, RedpandaMetadata},
+    partitioner::Partitioner,
+    role::ClientRole,
+    spool::{ReplayCounts, SendOutcome, Spool},
+    throttle::{CircuitBreaker, RateLimiter},
+};
+use futures::{
+    channel::oneshot,
+    future::{self, BoxFuture},
+    stream::FuturesUnordered,
+    FutureExt, StreamExt,
+};
 use rdkafka::{
-    error::KafkaError,
-    message::OwnedHeaders,
-    producer::FutureProducer,
-    util::Timeout, Timestamp,
+    error::{KafkaError, RDKafkaErrorCode},
+    message::{Header, Message, OwnedHeaders, ToBytes},
+    producer::{BaseRecord, ThreadedProducer},
+    util::Timeout,
+    Timestamp,
 };
 use tracing::{event, instrument, Level};
 
-type TracingProducer = FutureProducer<TracingProducerContext>;
+/// Producer whose delivery reports go to a RedpandaContext and complete DeliveryFutures
+pub type ContextProducer<C> = ThreadedProducer<ContextAdapter<C>>;
+
+/// The record is handed back with the error so it can be retried, like FutureProducer::send_result
+type SendResult<'a> = Result<DeliveryFuture, (KafkaError, FutureRecord<'a, Vec<u8>, Vec<u8>>)>;
 
+pub use rdkafka::producer::future_producer::OwnedDeliveryResult;
+pub use rdkafka::producer::DeliveryResult;
 pub use rdkafka::producer::FutureRecord;
 pub use rdkafka::producer::Producer;
-pub use rdkafka::producer::DeliveryFuture;
+
+/// How long a topic's partition count is cached, librdkafka's default
+/// `topic.metadata.refresh.interval.ms`
+pub(crate) const PARTITION_COUNT_TTL: Duration = Duration::from_secs(300);
+/// Longest a partition count fetch may take, whatever the request timeout
+pub(crate) const PARTITION_COUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Backoff bounds while librdkafka's queue is full
+const QUEUE_FULL_BACKOFF_MIN: Duration = Duration::from_millis(10);
+const QUEUE_FULL_BACKOFF_MAX: Duration = Duration::from_secs(1);
+/// Times a send backs off on a full queue before failing the record with QueueFull
+pub const QUEUE_FULL_RETRIES: u32 = 10;
+/// Spooled records `replay_spool` keeps awaiting delivery, and how many it replays between
+/// saving its position
+pub const SPOOL_REPLAY_IN_FLIGHT: usize = 256;
+
+/// Resolves to the partition and offset a record was written to, or the error and the message
+/// if delivery failed
+///
+/// Resolves to Canceled if the producer is dropped before the delivery report arrives
+#[derive(Debug)]
+pub struct DeliveryFuture {
+    pub(crate) rx: oneshot::Receiver<OwnedDeliveryResult>,
+    /// Counts the result once it resolves, for records that skipped `observe`
+    pub(crate) circuit_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl Future for DeliveryFuture {
+    type Output = Result<OwnedDeliveryResult, oneshot::Canceled>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = self.rx.poll_unpin(cx);
+        if let Poll::Ready(result) = &result {
+            if let Some(circuit_breaker) = self.circuit_breaker.take() {
+                circuit_breaker.record(matches!(result, Ok(Ok(_))));
+            }
+        }
+
+        result
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct RedpandaRecord {
     topic: String,
+    partition: Option<i32>,
     key: Option<Vec<u8>>,
     payload: Vec<u8>,
     headers: Option<OwnedHeaders>,
@@ -29,12 +108,151 @@ impl RedpandaRecord {
     pub fn new(topic: &str, key: Option<Vec<u8>>, payload: Vec<u8>, headers: Option<OwnedHeaders>) -> Self {
         Self { 
             topic: topic.to_owned(),
+            partition: None,
             key,
             payload,
             headers,
             created_timestamp: Timestamp::now(),
         }
     }
+
+    /// Construct a RedpandaRecord from a typed key and payload
+    pub fn typed<K: ?Sized, V: ?Sized>(
+        topic: &str,
+        key: Option<&K>,
+        payload: &V,
+        key_serializer: &impl Serializer<K>,
+        payload_serializer: &impl Serializer<V>,
+    ) -> Result<Self, RedpandaError> {
+        let serialize_error = |part| {
+            move |source| RedpandaError::Serialize {
+                topic: topic.to_owned(),
+                part,
+                source,
+            }
+        };
+        let key = key
+            .map(|key| key_serializer.serialize(key))
+            .transpose()
+            .map_err(serialize_error(RecordPart::Key))?;
+        let payload = payload_serializer
+            .serialize(payload)
+            .map_err(serialize_error(RecordPart::Payload))?;
+
+        Ok(Self::new(topic, key, payload, None))
+    }
+
+    /// Copy a consumed message into a record for the same topic, to forward or replay it
+    ///
+    /// Keeps the key, payload, headers and timestamp; a null payload becomes an empty one
+    pub fn from_message<M: Message + ?Sized>(message: &M) -> Self {
+        Self {
+            topic: message.topic().to_owned(),
+            partition: None,
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().map(<[u8]>::to_vec).unwrap_or_default(),
+            headers: message
+                .headers()
+                .map(|headers| HeaderMap::from_headers(headers).to_owned_headers()),
+            created_timestamp: match message.timestamp() {
+                Timestamp::NotAvailable => Timestamp::now(),
+                timestamp => timestamp,
+            },
+        }
+    }
+
+    /// Send the record to another topic
+    pub fn with_topic(mut self, topic: &str) -> Self {
+        self.topic = topic.to_owned();
+
+        self
+    }
+
+    /// Append a header; strings and byte slices work as values
+    pub fn with_header<V: ToBytes + ?Sized>(mut self, key: &str, value: &V) -> Self {
+        let headers = self.headers.take().unwrap_or_default();
+        self.headers = Some(headers.insert(Header {
+            key,
+            value: Some(value),
+        }));
+
+        self
+    }
+
+    /// Append a header whose value is `value` serialized with `serializer`
+    pub fn with_header_as<T: ?Sized>(
+        self,
+        key: &str,
+        value: &T,
+        serializer: &impl Serializer<T>,
+    ) -> Result<Self, RedpandaError> {
+        let value = serializer
+            .serialize(value)
+            .map_err(|source| RedpandaError::Serialize {
+                topic: self.topic.clone(),
+                part: RecordPart::Header,
+                source,
+            })?;
+
+        Ok(self.with_header(key, &value))
+    }
+
+    pub(crate) fn with_payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+
+        self
+    }
+
+    pub(crate) fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.created_timestamp = timestamp;
+
+        self
+    }
+
+    /// Send the record to `partition`, bypassing the producer's partitioner
+    pub fn with_partition(mut self, partition: i32) -> Self {
+        self.partition = Some(partition);
+
+        self
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// The partition set with `with_partition`
+    pub fn partition(&self) -> Option<i32> {
+        self.partition
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn headers(&self) -> HeaderMap {
+        self.headers
+            .as_ref()
+            .map(HeaderMap::from)
+            .unwrap_or_default()
+    }
+
+    /// When the record was created, the timestamp it's produced with
+    pub fn timestamp(&self) -> Timestamp {
+        self.created_timestamp
+    }
+}
+
+/// Where a record sent by `send_all` was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveredRecord {
+    pub partition: i32,
+    pub offset: i64,
+    /// The record's create time
+    pub timestamp: Timestamp,
 }
 
 impl<'a> From<&'a RedpandaRecord> for FutureRecord<'a, Vec<u8>, Vec<u8>> {
@@ -45,7 +263,7 @@ impl<'a> From<&'a RedpandaRecord> for FutureRecord<'a, Vec<u8>, Vec<u8>> {
     fn from(r: &'a RedpandaRecord) -> Self { 
         FutureRecord {
             topic: &r.topic,
-            partition: Option::None,
+            partition: r.partition,
             payload: Some(&r.payload),
             key: r.key.as_ref(),
             timestamp: r.created_timestamp.to_millis(),
@@ -54,36 +272,235 @@ impl<'a> From<&'a RedpandaRecord> for FutureRecord<'a, Vec<u8>, Vec<u8>> {
     }
 }
 
-/// Derive Clone is fine because the underlying rdkafka::producer::FutureProducer is meant
-/// to be cloned cheaply.
-/// 
-/// rdkafka::producer::FutureProducer docs:
-/// "It [a FutureProducer] can be cheaply cloned to get a reference to the same underlying producer."
-/// 
-/// ref: https://docs.rs/rdkafka/latest/rdkafka/producer/struct.FutureProducer.html
-#[derive(Clone)]
-pub struct RedpandaProducer {
-    pub producer: TracingProducer,
+/// Clones are cheap and share the same underlying producer, like rdkafka's FutureProducer
+///
+/// The producer's polling thread calls the context's `delivery` callback for every record, then
+/// completes the record's DeliveryFuture.
+pub struct RedpandaProducer<C: RedpandaContext = TracingContext> {
+    pub producer: Arc<ContextProducer<C>>,
+    pub(crate) request_timeout: Timeout,
+    effective_config: EffectiveConfig,
+    partitioner: Option<Arc<dyn Partitioner>>,
+    partition_counts: Arc<Mutex<HashMap<String, (i32, Instant)>>>,
+    closed: Arc<AtomicBool>,
+    spool: Option<Arc<Spool>>,
+    claim_check: Option<Arc<ClaimCheck>>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Arc<Encryption>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    interceptors: Interceptors,
 }
 
-impl RedpandaProducer {
-    /// Create a new RedpandaProducer
-    #[instrument(skip(producer))]
-    pub fn new(producer: TracingProducer, request_timeout: Timeout) -> Result<Self, KafkaError> {
-        let client = producer.client();
-        match client.fetch_metadata(Option::None, request_timeout) {
-            Ok(m) => {
-                let m: RedpandaMetadata = m.into();
+impl<C: RedpandaContext> Clone for RedpandaProducer<C> {
+    fn clone(&self) -> Self {
+        Self {
+            producer: self.producer.clone(),
+            request_timeout: self.request_timeout,
+            effective_config: self.effective_config.clone(),
+            partitioner: self.partitioner.clone(),
+            partition_counts: self.partition_counts.clone(),
+            closed: self.closed.clone(),
+            spool: self.spool.clone(),
+            claim_check: self.claim_check.clone(),
+            #[cfg(feature = "encryption")]
+            encryption: self.encryption.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            interceptors: self.interceptors.clone(),
+        }
+    }
+}
+
+impl<C: RedpandaContext> Drop for RedpandaProducer<C> {
+    /// Records still queued when the last clone is dropped are never delivered
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.producer) == 1 {
+            let undelivered = self.producer.in_flight_count();
+            if undelivered > 0 {
                 event!(
-                    Level::INFO,
-                    "Connected consumer to Redpanda cluster {:?}",
-                    m
+                    Level::WARN,
+                    "Dropped producer with {} undelivered records, close it to flush them",
+                    undelivered
                 );
-                m
             }
-            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl<C: RedpandaContext> RedpandaProducer<C> {
+    /// Create a new RedpandaProducer
+    #[instrument(skip(producer))]
+    pub fn new(producer: ContextProducer<C>, request_timeout: Timeout) -> Result<Self, KafkaError> {
+        check_connection(producer.client(), ClientRole::Producer, request_timeout)?;
+        Ok(Self {
+            producer: Arc::new(producer),
+            request_timeout,
+            effective_config: EffectiveConfig::default(),
+            partitioner: None,
+            partition_counts: Arc::new(Mutex::new(HashMap::new())),
+            closed: Arc::new(AtomicBool::new(false)),
+            spool: None,
+            claim_check: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            rate_limiter: None,
+            circuit_breaker: None,
+            interceptors: Arc::new([]),
+        })
+    }
+
+    /// Pick partitions with `partitioner` for records that don't set one
+    pub fn with_partitioner(mut self, partitioner: Arc<dyn Partitioner>) -> Self {
+        self.partitioner = Some(partitioner);
+
+        self
+    }
+
+    /// Spool records `send_or_spool` can't deliver to `spool`
+    pub fn with_spool(mut self, spool: Arc<Spool>) -> Self {
+        self.spool = Some(spool);
+
+        self
+    }
+
+    /// The spool set with `with_spool`
+    pub fn spool(&self) -> Option<&Arc<Spool>> {
+        self.spool.as_ref()
+    }
+
+    /// Check in oversized payloads with `claim_check` on every send path but `send_result`,
+    /// which fails the records it would check in
+    pub fn with_claim_check(mut self, claim_check: Arc<ClaimCheck>) -> Self {
+        self.claim_check = Some(claim_check);
+
+        self
+    }
+
+    /// Encrypt payloads with `encryption` on every send path but `send_result`, which fails the
+    /// records it would encrypt
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, encryption: Arc<Encryption>) -> Self {
+        self.encryption = Some(encryption);
+
+        self
+    }
+
+    /// Delay records over `rate_limiter`'s limits in `send`, `send_all` and `send_or_spool`, and
+    /// fail them with QueueFull in `send_result`
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+
+        self
+    }
+
+    /// Fail fast with CircuitOpen after consecutive delivery failures, or with Retry in
+    /// `send_result`
+    pub fn with_circuit_breaker(mut self, circuit_breaker: Arc<CircuitBreaker>) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+
+        self
+    }
+
+    pub fn circuit_breaker(&self) -> Option<&Arc<CircuitBreaker>> {
+        self.circuit_breaker.as_ref()
+    }
+
+    /// Run `interceptors`' `on_send`; their `on_ack` is run by the producer's context
+    pub(crate) fn with_interceptors(mut self, interceptors: Interceptors) -> Self {
+        self.interceptors = interceptors;
+
+        self
+    }
+
+    pub(crate) fn with_effective_config(mut self, config: EffectiveConfig) -> Self {
+        self.effective_config = config;
+
+        self
+    }
+
+    /// The context receiving this producer's callbacks
+    pub fn context(&self) -> &Arc<C> {
+        self.producer.context().context()
+    }
+
+    /// Receive an event for every record this producer delivers or fails to deliver from now on
+    ///
+    /// Events are shared by every clone of this producer. A subscriber more than
+    /// DELIVERY_EVENT_CAPACITY events behind misses the oldest ones and gets
+    /// `RecvError::Lagged`.
+    pub fn delivery_events(&self) -> tokio::sync::broadcast::Receiver<DeliveryEvent> {
+        self.producer.context().deliveries().subscribe()
+    }
+
+    /// Delivery counters of every topic this producer has sent records to
+    pub fn delivery_stats(&self) -> HashMap<String, DeliveryStats> {
+        self.producer.context().deliveries().stats()
+    }
+
+    /// The properties this producer was built with, secrets redacted
+    ///
+    /// Empty unless the producer was built by a RedpandaBuilder
+    pub fn effective_config(&self) -> &EffectiveConfig {
+        &self.effective_config
+    }
+
+    /// Number of partitions of `topic` from the cluster's metadata, cached for 5 minutes
+    ///
+    /// `None` if the topic doesn't exist. A cache miss fetches the topic's metadata on the
+    /// blocking thread pool, waiting for up to the request timeout or 10 seconds, whichever is
+    /// shorter.
+    pub async fn partition_count(&self, topic: &str) -> Result<Option<i32>, RedpandaError> {
+        if let Some(count) = self.cached_partition_count(topic) {
+            return Ok(Some(count));
+        }
+        let producer = self.producer.clone();
+        let owned_topic = topic.to_owned();
+        let timeout = match self.request_timeout {
+            Timeout::After(timeout) => timeout.min(PARTITION_COUNT_TIMEOUT),
+            Timeout::Never => PARTITION_COUNT_TIMEOUT,
+        };
+        let metadata: RedpandaMetadata = tokio::task::spawn_blocking(move || {
+            producer
+                .client()
+                .fetch_metadata(Some(&owned_topic), timeout)
+        })
+        .await??
+        .into();
+        let count = metadata.partition_count(topic);
+        let mut partition_counts = self.partition_counts.lock().unwrap();
+        match count {
+            Some(count) => partition_counts.insert(topic.to_owned(), (count, Instant::now())),
+            None => partition_counts.remove(topic),
+        };
+
+        Ok(count)
+    }
+
+    /// `topic`'s partition count if it was fetched less than 5 minutes ago
+    fn cached_partition_count(&self, topic: &str) -> Option<i32> {
+        let partition_counts = self.partition_counts.lock().unwrap();
+        let (count, fetched) = partition_counts.get(topic)?;
+
+        (fetched.elapsed() < PARTITION_COUNT_TTL).then_some(*count)
+    }
+
+    /// The record's own partition, else the partitioner's choice, else None to leave it to
+    /// librdkafka
+    async fn choose_partition(
+        &self,
+        record: &RedpandaRecord,
+    ) -> Result<Option<i32>, RedpandaError> {
+        let partitioner = match (record.partition, &self.partitioner) {
+            (Some(partition), _) => return Ok(Some(partition)),
+            (None, None) => return Ok(None),
+            (None, Some(partitioner)) => partitioner,
         };
-        Ok(Self { producer })
+        // Unknown topics are left to librdkafka, which may create them or fail the delivery
+        Ok(self
+            .partition_count(&record.topic)
+            .await?
+            .map(|count| partitioner.partition(&record.topic, record.key(), count)))
     }
 
     /// Re-implementation of FutureProducer.send_result that takes a RedpandaRecord instead of a FutureRecord
@@ -91,10 +508,561 @@ impl RedpandaProducer {
     /// RedpandaRecords are normal structs that own all their data & are much nicer to pass around vs FutureRecords
     /// that don't own the data in topic, payload, and key. These design decisions in rdkafka make it necessary
     /// to have a separate RedpandaRecord struct and implement the From trait
-    pub fn send_result<'a>(&self, record: &'a RedpandaRecord) -> 
-        Result<DeliveryFuture, (KafkaError, FutureRecord<'a, Vec<u8>, Vec<u8>>)> 
+    ///
+    /// Queues the record without waiting, after running it through the interceptors. An
+    /// interceptor rejecting it fails it with Application. `send`'s async stages can't run
+    /// here: a record that would be encrypted or checked in fails with UnsupportedFeature, so
+    /// send those with `enqueue`. With a partitioner, topics whose partition count isn't cached
+    /// are left to librdkafka's partitioner; await `partition_count` to fetch it.
+    ///
+    /// Fails with QueueFull rather than wait for a rate limit, with Retry while the circuit
+    /// breaker is open and with BrokerDestroy once the producer is closed. The circuit breaker
+    /// counts the delivery once the returned future resolves.
+    #[allow(clippy::result_large_err)]
+    pub fn send_result<'a>(&self, record: &'a RedpandaRecord) -> SendResult<'a> {
+        let fail = |e| (e, record.into());
+        let intercepted;
+        let prepared = match self.interceptors.is_empty() {
+            true => record,
+            false => match interceptor::on_send(&self.interceptors, record.clone()) {
+                Ok(record) => {
+                    intercepted = record;
+                    &intercepted
+                }
+                Err(e) => {
+                    event!(Level::WARN, "send_result failed: {}", e);
+                    return Err(fail(KafkaError::MessageProduction(
+                        RDKafkaErrorCode::Application,
+                    )));
+                }
+            },
+        };
+        if self.needs_async_stage(prepared) {
+            event!(
+                Level::WARN,
+                "send_result can't encrypt or check in records, use enqueue for topic {}",
+                prepared.topic
+            );
+            return Err(fail(KafkaError::MessageProduction(
+                RDKafkaErrorCode::UnsupportedFeature,
+            )));
+        }
+        self.try_admit(prepared).map_err(fail)?;
+        let partition = match (prepared.partition, &self.partitioner) {
+            (None, Some(partitioner)) => self
+                .cached_partition_count(&prepared.topic)
+                .map(|count| partitioner.partition(&prepared.topic, prepared.key(), count)),
+            (partition, _) => partition,
+        };
+
+        let delivery = self.queue(prepared, partition).map_err(fail)?;
+
+        Ok(DeliveryFuture {
+            circuit_breaker: self.circuit_breaker.clone(),
+            ..delivery
+        })
+    }
+
+    /// `admit` without waiting: a record over the rate limits fails with QueueFull and one
+    /// refused by the circuit breaker with Retry. The rate limits go first so a record they
+    /// fail doesn't use up the circuit breaker's probe.
+    fn try_admit(&self, record: &RedpandaRecord) -> Result<(), KafkaError> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let bytes = record.key().map_or(0, <[u8]>::len) + record.payload().len();
+            if !rate_limiter.try_reserve(record.topic(), bytes) {
+                return Err(KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull));
+            }
+        }
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            if let Err(e) = circuit_breaker.admit() {
+                event!(Level::WARN, "send_result failed: {}", e);
+                return Err(KafkaError::MessageProduction(RDKafkaErrorCode::Retry));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `send` would encrypt or check in `record`, which needs awaiting
+    fn needs_async_stage(&self, record: &RedpandaRecord) -> bool {
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            if encryption.encrypts(&record.topic) {
+                return true;
+            }
+        }
+
+        self.claim_check
+            .as_ref()
+            .is_some_and(|claim_check| claim_check.is_oversized(record))
+    }
+
+    /// Hand `record` to librdkafka for `partition`
+    fn queue(
+        &self,
+        record: &RedpandaRecord,
+        partition: Option<i32>,
+    ) -> Result<DeliveryFuture, KafkaError> {
+        if self.is_closed() {
+            return Err(KafkaError::MessageProduction(
+                RDKafkaErrorCode::BrokerDestroy,
+            ));
+        }
+        let (pending, delivery) = PendingDelivery::intercepted();
+        self.producer
+            .send(base_record(record.into(), partition, pending))
+            .map(|()| delivery)
+            .map_err(|(e, _)| e)
+    }
+
+    /// Serialize a key and payload into a record for `topic`, send it and wait for its delivery
+    ///
+    /// Serialization failures are RedpandaError::Serialize and nothing is sent
+    pub async fn send_typed<K: ?Sized, V: ?Sized>(
+        &self,
+        topic: &str,
+        key: Option<&K>,
+        payload: &V,
+        key_serializer: &impl Serializer<K>,
+        payload_serializer: &impl Serializer<V>,
+    ) -> Result<DeliveredRecord, RedpandaError> {
+        let record =
+            RedpandaRecord::typed(topic, key, payload, key_serializer, payload_serializer)?;
+
+        self.send(&record).await
+    }
+
+    /// Send `record` and wait for its delivery
+    ///
+    /// The record goes through the interceptors first. With encryption, the payload is then
+    /// encrypted. With a claim check, an oversized payload is then stored and the record sent
+    /// without it.
+    pub async fn send(&self, record: &RedpandaRecord) -> Result<DeliveredRecord, RedpandaError> {
+        let record = self.prepare(record).await?;
+
+        self.deliver(&record).await
+    }
+
+    /// Admit a prepared record, queue it and wait for its delivery
+    async fn deliver(&self, record: &RedpandaRecord) -> Result<DeliveredRecord, RedpandaError> {
+        self.admit(record).await?;
+        let partition = self.choose_partition(record).await?;
+        let delivery = self.queue(record, partition)?;
+
+        self.observe(delivered(record, delivery.await))
+    }
+
+    /// Fail if the circuit breaker is open, then wait for the rate limits
+    async fn admit(&self, record: &RedpandaRecord) -> Result<(), RedpandaError> {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.admit()?;
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let bytes = record.key().map_or(0, <[u8]>::len) + record.payload().len();
+            rate_limiter.acquire(record.topic(), bytes).await;
+        }
+
+        Ok(())
+    }
+
+    /// Count a delivery result towards the circuit breaker
+    fn observe(
+        &self,
+        result: Result<DeliveredRecord, RedpandaError>,
+    ) -> Result<DeliveredRecord, RedpandaError> {
+        if let Some(circuit_breaker) = &self.circuit_breaker {
+            circuit_breaker.record(result.is_ok());
+        }
+
+        result
+    }
+
+    /// Run `record` through the interceptors, then encrypt and check it in as configured
+    async fn prepare<'a>(
+        &self,
+        record: &'a RedpandaRecord,
+    ) -> Result<Cow<'a, RedpandaRecord>, RedpandaError> {
+        let mut record = match self.interceptors.is_empty() {
+            true => Cow::Borrowed(record),
+            false => Cow::Owned(interceptor::on_send(&self.interceptors, record.clone())?),
+        };
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.encryption {
+            if let Some(encrypted) = changed(encryption.encrypt(&record).await?) {
+                record = Cow::Owned(encrypted);
+            }
+        }
+        if let Some(claim_check) = &self.claim_check {
+            if let Some(checked_in) = changed(claim_check.check_in(&record).await?) {
+                record = Cow::Owned(checked_in);
+            }
+        }
+
+        Ok(record)
+    }
+
+    /// Prepare and admit `record` like `send`, then queue it, backing off while the queue is
+    /// full like `send_all`
+    ///
+    /// Resolves once librdkafka has the record, to the future of its delivery. Unlike
+    /// `send_result`, every stage of `send` applies.
+    pub async fn enqueue(
+        &self,
+        record: RedpandaRecord,
+    ) -> Result<BoxFuture<'static, Result<DeliveredRecord, RedpandaError>>, RedpandaError> {
+        let record = changed(self.prepare(&record).await?).unwrap_or(record);
+
+        self.enqueue_prepared(record).await
+    }
+
+    /// `enqueue` for a record that's been prepared already
+    async fn enqueue_prepared(
+        &self,
+        record: RedpandaRecord,
+    ) -> Result<BoxFuture<'static, Result<DeliveredRecord, RedpandaError>>, RedpandaError> {
+        self.admit(&record).await?;
+        let partition = self.choose_partition(&record).await?;
+        let delivery =
+            retry_queue_full(|| self.queue(&record, partition), tokio::time::sleep).await?;
+        let producer = self.clone();
+
+        Ok(async move { producer.observe(delivered(&record, delivery.await)) }.boxed())
+    }
+
+    /// Send every record, keeping at most `max_in_flight` of them awaiting delivery, and return
+    /// each record's result in the order of `records`
+    ///
+    /// Records are intercepted, encrypted and checked in like `send`.
+    ///
+    /// When librdkafka's queue is full, backs off while the records in flight are delivered,
+    /// failing the record with QueueFull after QUEUE_FULL_RETRIES retries. Other errors,
+    /// CircuitOpen included, fail only the record they're for.
+    pub async fn send_all(
+        &self,
+        records: &[RedpandaRecord],
+        max_in_flight: usize,
+    ) -> Vec<Result<DeliveredRecord, RedpandaError>> {
+        let max_in_flight = max_in_flight.max(1);
+        let mut results: Vec<Option<Result<DeliveredRecord, RedpandaError>>> =
+            records.iter().map(|_| None).collect();
+        let mut in_flight = FuturesUnordered::new();
+
+        for (i, record) in records.iter().enumerate() {
+            if in_flight.len() >= max_in_flight {
+                if let Some((j, result)) = in_flight.next().await {
+                    results[j] = Some(result);
+                }
+            }
+            let prepared = match self.prepare(record).await {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    results[i] = Some(Err(e));
+                    continue;
+                }
+            };
+            if let Err(e) = self.admit(&prepared).await {
+                results[i] = Some(Err(e));
+                continue;
+            }
+            let partition = match self.choose_partition(&prepared).await {
+                Ok(partition) => partition,
+                Err(e) => {
+                    results[i] = Some(Err(e));
+                    continue;
+                }
+            };
+            // Deliveries complete on the producer's polling thread, freeing up the queue
+            match retry_queue_full(|| self.queue(&prepared, partition), tokio::time::sleep).await {
+                Ok(delivery) => {
+                    in_flight.push(delivery.map(move |r| (i, self.observe(delivered(record, r)))))
+                }
+                Err(e) => results[i] = Some(Err(e.into())),
+            }
+        }
+        while let Some((j, result)) = in_flight.next().await {
+            results[j] = Some(result);
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.expect("every record is sent or failed"))
+            .collect()
+    }
+
+    /// Send `record` and wait for its delivery, or append it to the spool if the brokers can't
+    /// be reached
+    ///
+    /// Records are spooled when the queue is full, delivery times out, the brokers are down,
+    /// the circuit breaker is open or the producer is closed; other errors are returned. While
+    /// the spool holds records, new ones are spooled too so they're replayed in order. Without
+    /// a spool this is a plain send.
+    pub async fn send_or_spool(
+        &self,
+        record: &RedpandaRecord,
+    ) -> Result<SendOutcome, RedpandaError> {
+        let record = &*self.prepare(record).await?;
+        if let Some(spool) = self.spool.as_ref().filter(|spool| !spool.is_empty()) {
+            spool.append(record).await?;
+            return Ok(SendOutcome::Spooled);
+        }
+        match (self.deliver(record).await, &self.spool) {
+            (Err(e), Some(spool)) if is_unreachable(&e) => {
+                event!(Level::DEBUG, "Spooling record for {}: {}", record.topic, e);
+                spool.append(record).await?;
+                Ok(SendOutcome::Spooled)
+            }
+            (result, _) => result.map(SendOutcome::Delivered),
+        }
+    }
+
+    /// Send the spooled records in order, keeping up to SPOOL_REPLAY_IN_FLIGHT of them awaiting
+    /// delivery, and return how many were delivered
+    ///
+    /// Stops at the first record that can't reach the brokers, which stays first in the spool.
+    /// Records after it that were already sent are sent again by the next replay, so a record
+    /// may be delivered twice. Records the cluster rejects for good, e.g. as too large, are
+    /// dropped and counted in the spool's `failed` metric. The replay position is saved every
+    /// SPOOL_REPLAY_IN_FLIGHT records and when the replay ends. Fails without sending anything
+    /// if the cluster's metadata can't be fetched within the request timeout.
+    pub async fn replay_spool(&self) -> Result<u64, RedpandaError> {
+        let spool = match &self.spool {
+            Some(spool) if !spool.is_empty() => spool,
+            _ => return Ok(0),
+        };
+        let _replay = spool.replay.lock().await;
+        let producer = self.producer.clone();
+        let timeout = self.request_timeout;
+        tokio::task::spawn_blocking(move || producer.client().fetch_metadata(None, timeout))
+            .await??;
+
+        let mut position = spool.cursor().await?;
+        let mut reading = true;
+        let mut in_flight = VecDeque::new();
+        let mut acked = ReplayCounts::default();
+        let mut acked_next = position;
+        let mut replayed = 0;
+        let result = loop {
+            while reading && in_flight.len() < SPOOL_REPLAY_IN_FLIGHT {
+                let (record, next) = match spool.read(position).await? {
+                    Some(entry) => entry,
+                    None => {
+                        reading = false;
+                        break;
+                    }
+                };
+                position = next;
+                let topic = record.as_ref().map(|record| record.topic.clone());
+                let delivery = match record {
+                    None => future::ready(None).boxed(),
+                    Some(record) => match self.enqueue_prepared(record).await {
+                        Ok(delivery) => delivery.map(Some).boxed(),
+                        Err(e) => {
+                            // Sending the records after it would only reorder them
+                            reading = !stops_replay(&e);
+                            future::ready(Some(Err(e))).boxed()
+                        }
+                    },
+                };
+                in_flight.push_back((next, topic, delivery));
+            }
+            let (next, topic, delivery) = match in_flight.pop_front() {
+                Some(entry) => entry,
+                None => break Ok(()),
+            };
+            match delivery.await {
+                None => acked.corrupted += 1,
+                Some(Ok(_)) => acked.replayed += 1,
+                Some(Err(e)) if stops_replay(&e) => break Err(e),
+                Some(Err(e)) => {
+                    event!(
+                        Level::ERROR,
+                        "Dropping spooled record for {}: {}",
+                        topic.unwrap_or_default(),
+                        e
+                    );
+                    acked.failed += 1;
+                }
+            }
+            acked_next = next;
+            if acked.total() >= SPOOL_REPLAY_IN_FLIGHT as u64 {
+                spool.advance(acked_next, acked).await?;
+                replayed += acked.replayed;
+                acked = ReplayCounts::default();
+            }
+        };
+        if acked.total() > 0 {
+            spool.advance(acked_next, acked).await?;
+            replayed += acked.replayed;
+        }
+        event!(Level::INFO, "Replayed {} spooled records", replayed);
+
+        result.map(|()| replayed)
+    }
+
+    /// Replay the spool every `interval` while it holds records
+    ///
+    /// The task keeps a clone of the producer; abort it before closing the producer.
+    pub fn spawn_spool_replay(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let producer = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = producer.replay_spool().await {
+                    event!(Level::DEBUG, "Spool replay stopped: {}", e);
+                }
+            }
+        })
+    }
+    /// Stop accepting records and wait up to `timeout` for the queued ones to be delivered
+    ///
+    /// Returns the number of records and requests still undelivered, 0 if everything was
+    /// flushed. Closing affects every clone of the producer, and records sent afterwards fail
+    /// with BrokerDestroy. Undelivered records are dropped with the last clone, and their
+    /// DeliveryFutures resolve to Canceled.
+    pub async fn close(&self, timeout: Duration) -> usize {
+        if !self.closed.swap(true, Ordering::SeqCst) {
+            event!(Level::INFO, "Closing producer");
+        }
+        let producer = self.producer.clone();
+        let flushed = tokio::task::spawn_blocking(move || producer.flush(timeout)).await;
+        let undelivered = self.producer.in_flight_count().max(0) as usize;
+        match flushed {
+            Ok(Ok(())) => event!(Level::INFO, "Closed producer, every record was delivered"),
+            Ok(Err(e)) => event!(
+                Level::WARN,
+                "Closed producer with {} undelivered records: {}",
+                undelivered,
+                e
+            ),
+            Err(e) => event!(Level::ERROR, "Flushing the producer failed: {}", e),
+        }
+
+        undelivered
+    }
+
+    /// Whether `close` was called on this producer or a clone of it
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Wait for SIGINT or SIGTERM, then close the producer, giving it `timeout` to drain
+    ///
+    /// Spawn it when the service starts; see `close` for the result.
+    pub async fn close_on_shutdown(&self, timeout: Duration) -> std::io::Result<usize> {
+        shutdown_signal().await?;
+        event!(Level::INFO, "Shutdown signal received");
+
+        Ok(self.close(timeout).await)
+    }
+}
+
+/// Resolves on SIGINT (Ctrl-C) or, on Unix, SIGTERM
+pub async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+    #[cfg(not(unix))]
     {
-        self.producer.send_result(record.into())
+        tokio::signal::ctrl_c().await
+    }
+}
+
+/// Whether a record that failed with `error` may be delivered later, so `send_or_spool` spools
+/// it and `replay_spool` keeps it
+fn is_unreachable(error: &RedpandaError) -> bool {
+    matches!(
+        error,
+        RedpandaError::Kafka(KafkaError::Canceled) | RedpandaError::CircuitOpen { .. }
+    ) || matches!(
+        error.rdkafka_error_code(),
+        Some(
+            RDKafkaErrorCode::QueueFull
+                | RDKafkaErrorCode::MessageTimedOut
+                | RDKafkaErrorCode::AllBrokersDown
+                | RDKafkaErrorCode::BrokerTransportFailure
+                | RDKafkaErrorCode::RequestTimedOut
+                | RDKafkaErrorCode::BrokerDestroy
+        )
+    )
+}
+
+/// Whether `replay_spool` stops at a record that failed with `error` rather than drop it.
+/// Errors without an rdkafka code didn't come from the cluster.
+fn stops_replay(error: &RedpandaError) -> bool {
+    is_unreachable(error) || error.rdkafka_error_code().is_none()
+}
+
+/// The new record if a step of `prepare` changed it
+fn changed(record: Cow<'_, RedpandaRecord>) -> Option<RedpandaRecord> {
+    match record {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(record) => Some(record),
+    }
+}
+
+/// A BaseRecord for `record` sent to `partition`, whose delivery report completes `pending`
+pub(crate) fn base_record<'a>(
+    record: FutureRecord<'a, Vec<u8>, Vec<u8>>,
+    partition: Option<i32>,
+    pending: PendingDelivery,
+) -> BaseRecord<'a, Vec<u8>, Vec<u8>, Box<PendingDelivery>> {
+    BaseRecord {
+        topic: record.topic,
+        partition,
+        payload: record.payload,
+        key: record.key,
+        timestamp: record.timestamp,
+        headers: record.headers,
+        delivery_opaque: Box::new(pending),
+    }
+}
+
+/// Turn a record's DeliveryFuture output into its `send_all` result
+pub(crate) fn delivered(
+    record: &RedpandaRecord,
+    result: Result<OwnedDeliveryResult, oneshot::Canceled>,
+) -> Result<DeliveredRecord, RedpandaError> {
+    match result {
+        Ok(Ok((partition, offset))) => Ok(DeliveredRecord {
+            partition,
+            offset,
+            timestamp: record.created_timestamp,
+        }),
+        Ok(Err((e, _))) => Err(e.into()),
+        Err(oneshot::Canceled) => Err(KafkaError::Canceled.into()),
+    }
+}
+
+/// Call `send` until it doesn't fail with QueueFull, giving up after QUEUE_FULL_RETRIES
+/// retries
+///
+/// `wait` is given a backoff doubling from 10ms to 1s between tries, e.g. `tokio::time::sleep`
+/// or a closure serving delivery reports.
+pub(crate) async fn retry_queue_full<T, S, W, F>(mut send: S, mut wait: W) -> Result<T, KafkaError>
+where
+    S: FnMut() -> Result<T, KafkaError>,
+    W: FnMut(Duration) -> F,
+    F: Future<Output = ()>,
+{
+    let mut backoff = QUEUE_FULL_BACKOFF_MIN;
+    for _ in 0..QUEUE_FULL_RETRIES {
+        match send() {
+            Err(KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull)) => {
+                event!(Level::DEBUG, "Queue full, retrying in {:?}", backoff);
+                wait(backoff).await;
+                backoff = (backoff * 2).min(QUEUE_FULL_BACKOFF_MAX);
+            }
+            result => return result,
+        }
     }
 
+    send()
 }
@@ -0,0 +1,82 @@
+/// Coherent groups of settings tuned for one goal, applied over RedpandaBuilder::default()
+///
+/// Profiles set both producer and consumer keys; a client ignores the keys meant for the other.
+/// Anything a profile sets can still be overridden with the builder's setters afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Send every message as soon as it's produced and fetch as soon as anything arrives
+    ///
+    /// Trade-offs: no batching or compression, so more requests and more bytes on the wire;
+    /// `acks=1` means a message acknowledged by the leader can be lost if the leader fails
+    /// before replicating it, and idempotence is disabled because it requires `acks=all`.
+    LowLatency,
+    /// Large, lz4-compressed batches and large fetches
+    ///
+    /// Trade-offs: messages wait up to `linger.ms` (100ms) to fill a batch and consumers wait
+    /// up to `fetch.wait.max.ms` (500ms) to fill a fetch, and the client buffers more memory.
+    HighThroughput,
+    /// Idempotent producers and consumers that only see committed transactional messages
+    ///
+    /// Trade-offs: auto commit is disabled, so the application must commit offsets itself
+    /// (normally inside a producer transaction); read_committed consumers can't read past an
+    /// open transaction, which adds end-to-end latency.
+    ExactlyOnce,
+    /// At-least-once delivery that survives broker failures
+    ///
+    /// Trade-offs: producers retry indefinitely within `message.timeout.ms` (10 minutes), so a
+    /// long outage shows up as slow deliveries rather than errors; auto offset store is
+    /// disabled, so the application must call `store_offset` after processing each message or
+    /// nothing gets committed.
+    Durable,
+}
+
+impl Profile {
+    /// The librdkafka settings this profile applies
+    pub fn settings(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Profile::LowLatency => &[
+                ("linger.ms", "0"),
+                ("compression.type", "none"),
+                ("acks", "1"),
+                ("enable.idempotence", "false"),
+                ("socket.nagle.disable", "true"),
+                ("fetch.wait.max.ms", "10"),
+                ("fetch.min.bytes", "1"),
+            ],
+            Profile::HighThroughput => &[
+                ("linger.ms", "100"),
+                ("batch.size", "1000000"),
+                ("batch.num.messages", "100000"),
+                ("compression.type", "lz4"),
+                ("queue.buffering.max.kbytes", "1048576"),
+                ("fetch.wait.max.ms", "500"),
+                ("fetch.min.bytes", "1048576"),
+                ("queued.min.messages", "1000000"),
+            ],
+            Profile::ExactlyOnce => &[
+                ("enable.idempotence", "true"),
+                ("acks", "all"),
+                ("max.in.flight.requests.per.connection", "5"),
+                ("isolation.level", "read_committed"),
+                ("enable.auto.commit", "false"),
+            ],
+            Profile::Durable => &[
+                ("enable.idempotence", "true"),
+                ("acks", "all"),
+                ("message.send.max.retries", "2147483647"),
+                ("retry.backoff.ms", "500"),
+                ("message.timeout.ms", "600000"),
+                ("enable.auto.offset.store", "false"),
+            ],
+        }
+    }
+}
+
+/// A config value changed by applying a Profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub key: String,
+    /// Value before the profile was applied, None if it was unset (librdkafka default)
+    pub before: Option<String>,
+    pub after: String,
+}
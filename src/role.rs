@@ -0,0 +1,143 @@
+use crate::config::EffectiveConfig;
+use crate::error::RedpandaError;
+
+/// librdkafka properties that only apply to consumers (`C` in librdkafka's CONFIGURATION.md)
+const CONSUMER_ONLY_KEYS: &[&str] = &[
+    "group.id",
+    "group.instance.id",
+    "partition.assignment.strategy",
+    "session.timeout.ms",
+    "heartbeat.interval.ms",
+    "group.protocol.type",
+    "coordinator.query.interval.ms",
+    "max.poll.interval.ms",
+    "enable.auto.commit",
+    "auto.commit.interval.ms",
+    "enable.auto.offset.store",
+    "queued.min.messages",
+    "queued.max.messages.kbytes",
+    "fetch.wait.max.ms",
+    "fetch.message.max.bytes",
+    "max.partition.fetch.bytes",
+    "fetch.max.bytes",
+    "fetch.min.bytes",
+    "fetch.error.backoff.ms",
+    "isolation.level",
+    "enable.partition.eof",
+    "check.crcs",
+    "auto.offset.reset",
+];
+
+/// librdkafka properties that only apply to producers (`P` in librdkafka's CONFIGURATION.md)
+const PRODUCER_ONLY_KEYS: &[&str] = &[
+    "transactional.id",
+    "transaction.timeout.ms",
+    "enable.idempotence",
+    "enable.gapless.guarantee",
+    "queue.buffering.max.messages",
+    "queue.buffering.max.kbytes",
+    "queue.buffering.max.ms",
+    "linger.ms",
+    "message.send.max.retries",
+    "retries",
+    "retry.backoff.ms",
+    "queue.buffering.backpressure.threshold",
+    "compression.codec",
+    "compression.type",
+    "compression.level",
+    "batch.num.messages",
+    "batch.size",
+    "delivery.report.only.error",
+    "sticky.partitioning.linger.ms",
+    "request.required.acks",
+    "acks",
+    "request.timeout.ms",
+    "message.timeout.ms",
+    "delivery.timeout.ms",
+    "partitioner",
+];
+
+/// The kind of client a config is built for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientRole {
+    Producer,
+    Consumer,
+    Admin,
+}
+
+impl ClientRole {
+    /// Whether librdkafka uses `key` for clients of this role
+    ///
+    /// Admin clients are neither producers nor consumers, so they take neither's keys
+    pub fn accepts(&self, key: &str) -> bool {
+        let consumer_only = CONSUMER_ONLY_KEYS.contains(&key);
+        let producer_only = PRODUCER_ONLY_KEYS.contains(&key);
+        match self {
+            ClientRole::Producer => !consumer_only,
+            ClientRole::Consumer => !producer_only,
+            ClientRole::Admin => !consumer_only && !producer_only,
+        }
+    }
+
+    /// Remove the keys this role doesn't use from `config`
+    pub(crate) fn drop_foreign_keys(&self, config: &mut EffectiveConfig) {
+        for key in CONSUMER_ONLY_KEYS.iter().chain(PRODUCER_ONLY_KEYS) {
+            if !self.accepts(key) {
+                config.remove(key);
+            }
+        }
+    }
+}
+
+/// Settings for one client role, layered over a RedpandaBuilder's common settings
+///
+/// Debug output redacts secret values
+#[derive(Debug, Clone)]
+pub struct ScopedConfig {
+    role: ClientRole,
+    overrides: EffectiveConfig,
+}
+
+impl ScopedConfig {
+    pub(crate) fn new(role: ClientRole) -> Self {
+        Self {
+            role,
+            overrides: EffectiveConfig::default(),
+        }
+    }
+
+    /// The role these settings apply to
+    pub fn role(&self) -> ClientRole {
+        self.role
+    }
+
+    /// Set a parameter for this role only, rejecting parameters the role doesn't use
+    pub fn set(&mut self, key: &str, value: &str) -> Result<&mut ScopedConfig, RedpandaError> {
+        if !self.role.accepts(key) {
+            return Err(RedpandaError::ClientConfig {
+                key: key.to_owned(),
+                message: format!("not used by {:?} clients", self.role),
+            });
+        }
+        self.overrides.set(key, value);
+
+        Ok(self)
+    }
+
+    /// Get a parameter set for this role, redacted if it's a secret
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.overrides.get(key)
+    }
+
+    /// Remove a parameter set for this role, falling back to the common setting
+    pub fn remove(&mut self, key: &str) -> &mut ScopedConfig {
+        self.overrides.remove(key);
+
+        self
+    }
+
+    /// Write the overrides over `config`
+    pub(crate) fn apply(&self, config: &mut EffectiveConfig) {
+        config.extend(&self.overrides);
+    }
+}
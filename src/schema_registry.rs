@@ -0,0 +1,435 @@
+//! Client for Redpanda's Schema Registry, which stores schemas in the `_schemas` topic and
+//! serves them over the Confluent Schema Registry HTTP API
+//!
+//! Requires the `schema-registry` feature. Codecs writing the registry's wire format are in
+//! `wire`; `mock` has an in-process registry for tests, behind the `schema-registry-mock`
+//! feature.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::sync::{Arc, Mutex};
+
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{event, instrument, Level};
+
+#[cfg(any(test, feature = "schema-registry-mock"))]
+pub mod mock;
+pub mod wire;
+
+use crate::codec::RecordPart;
+use crate::security::REDACTED;
+
+const CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+
+/// Error codes the registry returns for an unknown subject, version or schema
+pub const NOT_FOUND_ERROR_CODES: [i32; 3] = [40401, 40402, 40403];
+
+/// Subject of a topic's key or payload schemas under the default TopicNameStrategy, e.g.
+/// `orders-value`
+///
+/// TopicNameStrategy has no subject for headers, so they're a NoSubject error
+pub fn topic_subject(topic: &str, part: RecordPart) -> Result<String, SchemaRegistryError> {
+    match part {
+        RecordPart::Key => Ok(format!("{}-key", topic)),
+        RecordPart::Payload => Ok(format!("{}-value", topic)),
+        part => Err(SchemaRegistryError::NoSubject(part)),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SchemaRegistryError {
+    #[error("schema registry request failed")]
+    Http(#[from] reqwest::Error),
+    #[error("schema registry returned {status} (error code {error_code}): {message}")]
+    Api {
+        status: u16,
+        error_code: i32,
+        message: String,
+    },
+    #[error("invalid schema registry URL `{0}`")]
+    InvalidUrl(String),
+    #[error("invalid wire format: {0}")]
+    WireFormat(String),
+    #[error("schema {0} hasn't been fetched from the registry")]
+    SchemaNotCached(u32),
+    #[error("invalid schema: {0}")]
+    InvalidSchema(String),
+    #[error("TopicNameStrategy has no subject for a record's {0}")]
+    NoSubject(RecordPart),
+}
+
+impl SchemaRegistryError {
+    /// Whether the registry said the subject, version or schema doesn't exist
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, SchemaRegistryError::Api { error_code, .. } if NOT_FOUND_ERROR_CODES.contains(error_code))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaType {
+    #[default]
+    Avro,
+    Json,
+    Protobuf,
+}
+
+impl SchemaType {
+    fn is_avro(&self) -> bool {
+        *self == SchemaType::Avro
+    }
+}
+
+impl Display for SchemaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaType::Avro => write!(f, "AVRO"),
+            SchemaType::Json => write!(f, "JSON"),
+            SchemaType::Protobuf => write!(f, "PROTOBUF"),
+        }
+    }
+}
+
+/// A schema registered under another subject that a schema imports
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SchemaReference {
+    /// The type name (Avro), `$ref` (JSON Schema) or import path (Protobuf) it's referenced by
+    pub name: String,
+    pub subject: String,
+    pub version: u32,
+}
+
+/// A schema as the registry stores it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Schema {
+    pub schema: String,
+    /// Omitted by the registry for Avro schemas
+    #[serde(
+        rename = "schemaType",
+        default,
+        skip_serializing_if = "SchemaType::is_avro"
+    )]
+    pub schema_type: SchemaType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<SchemaReference>,
+}
+
+impl Schema {
+    pub fn new(schema_type: SchemaType, schema: &str) -> Self {
+        Self {
+            schema: schema.to_owned(),
+            schema_type,
+            references: Vec::new(),
+        }
+    }
+
+    pub fn avro(schema: &str) -> Self {
+        Self::new(SchemaType::Avro, schema)
+    }
+
+    pub fn json(schema: &str) -> Self {
+        Self::new(SchemaType::Json, schema)
+    }
+
+    pub fn protobuf(schema: &str) -> Self {
+        Self::new(SchemaType::Protobuf, schema)
+    }
+
+    pub fn with_reference(mut self, reference: SchemaReference) -> Self {
+        self.references.push(reference);
+
+        self
+    }
+}
+
+/// A version of a subject
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredSchema {
+    pub subject: String,
+    pub id: u32,
+    pub version: u32,
+    #[serde(flatten)]
+    pub schema: Schema,
+}
+
+/// Which version of a subject to fetch or check compatibility against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaVersion {
+    Latest,
+    Version(u32),
+}
+
+impl Display for SchemaVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaVersion::Latest => write!(f, "latest"),
+            SchemaVersion::Version(version) => write!(f, "{}", version),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IdResponse {
+    id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompatibilityResponse {
+    is_compatible: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error_code: i32,
+    message: String,
+}
+
+/// Everything the registry never changes: ids, and the schema of a subject's version
+#[derive(Debug, Default)]
+struct SchemaCache {
+    by_id: HashMap<u32, Schema>,
+    ids: HashMap<(String, Schema), u32>,
+    versions: HashMap<(String, u32), RegisteredSchema>,
+}
+
+/// Async Schema Registry client
+///
+/// Clones share a cache of schema ids and versions, which the registry never changes, so each
+/// is fetched once. Latest versions, subject lists and compatibility checks always go to the
+/// registry.
+#[derive(Clone)]
+pub struct SchemaRegistryClient {
+    http: reqwest::Client,
+    url: String,
+    basic_auth: Option<(String, String)>,
+    cache: Arc<Mutex<SchemaCache>>,
+}
+
+impl Debug for SchemaRegistryClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SchemaRegistryClient")
+            .field("url", &self.url)
+            .field(
+                "basic_auth",
+                &self
+                    .basic_auth
+                    .as_ref()
+                    .map(|(username, _)| (username, REDACTED)),
+            )
+            .finish()
+    }
+}
+
+impl SchemaRegistryClient {
+    /// Client for the registry at `url`, e.g. `http://localhost:8081`
+    pub fn new(url: &str) -> Result<Self, SchemaRegistryError> {
+        let url = url.trim_end_matches('/');
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(SchemaRegistryError::InvalidUrl(url.to_owned()));
+        }
+
+        Ok(Self {
+            http: reqwest::Client::new(),
+            url: url.to_owned(),
+            basic_auth: None,
+            cache: Arc::new(Mutex::new(SchemaCache::default())),
+        })
+    }
+
+    /// Authenticate with HTTP basic auth, e.g. with a Redpanda SASL user
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.basic_auth = Some((username.to_owned(), password.to_owned()));
+
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Register `schema` under `subject`, returning its id
+    ///
+    /// Registering a schema the subject already has returns the existing id
+    #[instrument(skip(self, schema))]
+    pub async fn register(
+        &self,
+        subject: &str,
+        schema: &Schema,
+    ) -> Result<u32, SchemaRegistryError> {
+        let key = (subject.to_owned(), schema.clone());
+        if let Some(id) = self.cache.lock().unwrap().ids.get(&key) {
+            return Ok(*id);
+        }
+        let path = format!("/subjects/{}/versions", encode(subject));
+        let response: IdResponse = self.request(Method::POST, &path, Some(schema)).await?;
+        event!(
+            Level::INFO,
+            "Registered schema {} under {}",
+            response.id,
+            subject
+        );
+        let mut cache = self.cache.lock().unwrap();
+        cache.ids.insert(key, response.id);
+        cache.by_id.insert(response.id, schema.clone());
+
+        Ok(response.id)
+    }
+
+    /// The version of `subject` that has `schema`
+    #[instrument(skip(self, schema))]
+    pub async fn lookup(
+        &self,
+        subject: &str,
+        schema: &Schema,
+    ) -> Result<RegisteredSchema, SchemaRegistryError> {
+        let path = format!("/subjects/{}", encode(subject));
+        let registered: RegisteredSchema = self.request(Method::POST, &path, Some(schema)).await?;
+        self.cache_version(&registered);
+
+        Ok(registered)
+    }
+
+    /// The schema with id `id`
+    #[instrument(skip(self))]
+    pub async fn schema_by_id(&self, id: u32) -> Result<Schema, SchemaRegistryError> {
+        if let Some(schema) = self.cached_schema(id) {
+            return Ok(schema);
+        }
+        let path = format!("/schemas/ids/{}", id);
+        let schema: Schema = self.request(Method::GET, &path, None).await?;
+        self.cache.lock().unwrap().by_id.insert(id, schema.clone());
+
+        Ok(schema)
+    }
+
+    /// The schema with id `id` if this client has already fetched or registered it
+    pub fn cached_schema(&self, id: u32) -> Option<Schema> {
+        self.cache.lock().unwrap().by_id.get(&id).cloned()
+    }
+
+    /// A version of `subject`
+    #[instrument(skip(self))]
+    pub async fn version(
+        &self,
+        subject: &str,
+        version: SchemaVersion,
+    ) -> Result<RegisteredSchema, SchemaRegistryError> {
+        if let SchemaVersion::Version(v) = version {
+            if let Some(registered) = self
+                .cache
+                .lock()
+                .unwrap()
+                .versions
+                .get(&(subject.to_owned(), v))
+            {
+                return Ok(registered.clone());
+            }
+        }
+        let path = format!("/subjects/{}/versions/{}", encode(subject), version);
+        let registered: RegisteredSchema = self.request(Method::GET, &path, None).await?;
+        self.cache_version(&registered);
+
+        Ok(registered)
+    }
+
+    /// Every subject in the registry
+    pub async fn subjects(&self) -> Result<Vec<String>, SchemaRegistryError> {
+        self.request(Method::GET, "/subjects", None).await
+    }
+
+    /// The versions of `subject`
+    pub async fn versions(&self, subject: &str) -> Result<Vec<u32>, SchemaRegistryError> {
+        let path = format!("/subjects/{}/versions", encode(subject));
+        self.request(Method::GET, &path, None).await
+    }
+
+    /// Whether `schema` could be registered as the next version of `subject`, under the
+    /// subject's compatibility level, checked against `version`
+    #[instrument(skip(self, schema))]
+    pub async fn is_compatible(
+        &self,
+        subject: &str,
+        version: SchemaVersion,
+        schema: &Schema,
+    ) -> Result<bool, SchemaRegistryError> {
+        let path = format!(
+            "/compatibility/subjects/{}/versions/{}",
+            encode(subject),
+            version
+        );
+        let response: CompatibilityResponse =
+            self.request(Method::POST, &path, Some(schema)).await?;
+
+        Ok(response.is_compatible)
+    }
+
+    fn cache_version(&self, registered: &RegisteredSchema) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.by_id.insert(registered.id, registered.schema.clone());
+        cache.ids.insert(
+            (registered.subject.clone(), registered.schema.clone()),
+            registered.id,
+        );
+        cache.versions.insert(
+            (registered.subject.clone(), registered.version),
+            registered.clone(),
+        );
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Schema>,
+    ) -> Result<T, SchemaRegistryError> {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.url, path))
+            .header(reqwest::header::ACCEPT, CONTENT_TYPE);
+        if let Some((username, password)) = &self.basic_auth {
+            request = request.basic_auth(username, Some(password));
+        }
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
+                .body(serde_json::to_vec(body).expect("schemas serialize to JSON"));
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let body = response.bytes().await?;
+        let (error_code, message) = match serde_json::from_slice::<ErrorResponse>(&body) {
+            Ok(e) => (e.error_code, e.message),
+            Err(_) => (
+                status.as_u16().into(),
+                String::from_utf8_lossy(&body).into_owned(),
+            ),
+        };
+        Err(SchemaRegistryError::Api {
+            status: status.as_u16(),
+            error_code,
+            message,
+        })
+    }
+}
+
+/// Percent-encode a subject for use as a path segment
+fn encode(subject: &str) -> String {
+    let mut encoded = String::with_capacity(subject.len());
+    for b in subject.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            b => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    encoded
+}
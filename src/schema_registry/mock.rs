@@ -0,0 +1,278 @@
+//! In-process Schema Registry for tests of code using SchemaRegistryClient
+//!
+//! Requires the `schema-registry-mock` feature outside this crate's own tests.
+
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{event, Level};
+
+use crate::schema_registry::{Schema, SchemaRegistryClient};
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Schema n has id n + 1
+    schemas: Vec<Schema>,
+    /// The ids of each subject's versions, in order
+    subjects: BTreeMap<String, Vec<u32>>,
+    incompatible: HashSet<String>,
+}
+
+/// In-process Schema Registry speaking the subset of the HTTP API SchemaRegistryClient uses
+///
+/// Schemas aren't parsed, so every schema is compatible unless a subject is marked otherwise
+/// with `set_compatible`. Shuts down when dropped.
+#[derive(Debug)]
+pub struct MockSchemaRegistry {
+    addr: SocketAddr,
+    registry: Arc<Mutex<Registry>>,
+    requests: Arc<AtomicUsize>,
+    server: JoinHandle<()>,
+}
+
+impl MockSchemaRegistry {
+    /// Start a registry on a free localhost port
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let registry = Arc::new(Mutex::new(Registry::default()));
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let server = {
+            let registry = registry.clone();
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                loop {
+                    let stream = match listener.accept().await {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            event!(Level::ERROR, "Mock schema registry accept failed: {}", e);
+                            continue;
+                        }
+                    };
+                    let registry = registry.clone();
+                    let requests = requests.clone();
+                    let service = service_fn(move |request| {
+                        requests.fetch_add(1, Ordering::SeqCst);
+                        handle(registry.clone(), request)
+                    });
+                    tokio::spawn(async move {
+                        let connection = http1::Builder::new()
+                            .serve_connection(TokioIo::new(stream), service)
+                            .await;
+                        if let Err(e) = connection {
+                            event!(Level::DEBUG, "Mock schema registry connection: {}", e);
+                        }
+                    });
+                }
+            })
+        };
+        event!(Level::INFO, "Started mock schema registry on {}", addr);
+
+        Ok(Self {
+            addr,
+            registry,
+            requests,
+            server,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A client for this registry
+    pub fn client(&self) -> SchemaRegistryClient {
+        SchemaRegistryClient::new(&self.url()).expect("the mock registry's URL is valid")
+    }
+
+    /// Number of HTTP requests served
+    pub fn request_count(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// Make new versions of `subject` compatible or incompatible with its existing ones
+    pub fn set_compatible(&self, subject: &str, compatible: bool) {
+        let mut registry = self.registry.lock().unwrap();
+        match compatible {
+            true => registry.incompatible.remove(subject),
+            false => registry.incompatible.insert(subject.to_owned()),
+        };
+    }
+}
+
+impl Drop for MockSchemaRegistry {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn handle(
+    registry: Arc<Mutex<Registry>>,
+    request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let body = match request.into_body().collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(_) => return Ok(error(50001, "failed to read the request body")),
+    };
+    let schema = serde_json::from_slice::<Schema>(&body);
+    let segments: Vec<String> = path.trim_matches('/').split('/').map(decode).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let mut registry = registry.lock().unwrap();
+
+    let response = match (&method, segments.as_slice()) {
+        (&Method::GET, ["subjects"]) => Ok(json!(registry.subjects.keys().collect::<Vec<_>>())),
+        (&Method::GET, ["subjects", subject, "versions"]) => registry
+            .versions(subject)
+            .map(|ids| json!((1..=ids.len()).collect::<Vec<_>>())),
+        (&Method::GET, ["subjects", subject, "versions", version]) => {
+            registry.version(subject, version)
+        }
+        (&Method::GET, ["schemas", "ids", id]) => registry.schema(id),
+        (&Method::POST, ["subjects", subject, "versions"]) => match schema {
+            Ok(schema) => registry.register(subject, schema),
+            Err(e) => Err((42201, e.to_string())),
+        },
+        (&Method::POST, ["subjects", subject]) => match schema {
+            Ok(schema) => registry.lookup(subject, &schema),
+            Err(e) => Err((42201, e.to_string())),
+        },
+        (&Method::POST, ["compatibility", "subjects", subject, "versions", version]) => registry
+            .version(subject, version)
+            .map(|_| json!({ "is_compatible": !registry.incompatible.contains(*subject) })),
+        _ => Err((40400, format!("{} {} not found", method, path))),
+    };
+
+    Ok(match response {
+        Ok(body) => Response::new(Full::new(Bytes::from(body.to_string()))),
+        Err((error_code, message)) => error(error_code, &message),
+    })
+}
+
+impl Registry {
+    fn versions(&self, subject: &str) -> Result<&Vec<u32>, (i32, String)> {
+        self.subjects
+            .get(subject)
+            .ok_or_else(|| (40401, format!("Subject '{}' not found.", subject)))
+    }
+
+    fn registered(&self, subject: &str, version: usize) -> Value {
+        let id = self.subjects[subject][version - 1];
+        let mut registered = serde_json::to_value(&self.schemas[id as usize - 1]).unwrap();
+        registered["subject"] = json!(subject);
+        registered["version"] = json!(version);
+        registered["id"] = json!(id);
+
+        registered
+    }
+
+    fn version(&self, subject: &str, version: &str) -> Result<Value, (i32, String)> {
+        let versions = self.versions(subject)?;
+        let version = match version {
+            "latest" => versions.len(),
+            version => version
+                .parse()
+                .ok()
+                .filter(|v| (1..=versions.len()).contains(v))
+                .ok_or_else(|| (40402, format!("Version {} not found.", version)))?,
+        };
+
+        Ok(self.registered(subject, version))
+    }
+
+    fn schema(&self, id: &str) -> Result<Value, (i32, String)> {
+        id.parse::<usize>()
+            .ok()
+            .and_then(|id| self.schemas.get(id.checked_sub(1)?))
+            .map(|schema| serde_json::to_value(schema).unwrap())
+            .ok_or_else(|| (40403, format!("Schema {} not found", id)))
+    }
+
+    fn register(&mut self, subject: &str, schema: Schema) -> Result<Value, (i32, String)> {
+        if let Ok(registered) = self.lookup(subject, &schema) {
+            return Ok(json!({ "id": registered["id"] }));
+        }
+        if self.subjects.contains_key(subject) && self.incompatible.contains(subject) {
+            return Err((
+                409,
+                format!("Schema being registered is incompatible with {}", subject),
+            ));
+        }
+        // Registering a schema under a second subject reuses its id
+        let id = match self.schemas.iter().position(|s| *s == schema) {
+            Some(position) => position as u32 + 1,
+            None => {
+                self.schemas.push(schema);
+                self.schemas.len() as u32
+            }
+        };
+        self.subjects
+            .entry(subject.to_owned())
+            .or_default()
+            .push(id);
+
+        Ok(json!({ "id": id }))
+    }
+
+    fn lookup(&self, subject: &str, schema: &Schema) -> Result<Value, (i32, String)> {
+        let versions = self.versions(subject)?;
+        versions
+            .iter()
+            .position(|id| self.schemas[*id as usize - 1] == *schema)
+            .map(|position| self.registered(subject, position + 1))
+            .ok_or_else(|| (40403, "Schema not found".to_owned()))
+    }
+}
+
+fn error(error_code: i32, message: &str) -> Response<Full<Bytes>> {
+    let body = json!({ "error_code": error_code, "message": message });
+    let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+    // Error codes start with their HTTP status
+    let status = match error_code {
+        409 => 409,
+        code => (code / 100) as u16,
+    };
+    *response.status_mut() =
+        StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    response
+}
+
+/// Percent-decode a path segment
+fn decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
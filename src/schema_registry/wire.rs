@@ -0,0 +1,358 @@
+//! The Confluent wire format: a zero magic byte and the big-endian schema id, then the encoded
+//! value. Protobuf values also carry the indexes of their message type in the schema.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{CodecError, Deserializer, Serializer};
+use crate::schema_registry::{Schema, SchemaRegistryClient, SchemaRegistryError};
+
+pub const MAGIC_BYTE: u8 = 0;
+
+/// Length of the magic byte and schema id
+pub const HEADER_LEN: usize = 5;
+
+/// Prefix `value` with the magic byte and `schema_id`
+pub fn encode(schema_id: u32, value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + value.len());
+    bytes.push(MAGIC_BYTE);
+    bytes.extend_from_slice(&schema_id.to_be_bytes());
+    bytes.extend_from_slice(value);
+
+    bytes
+}
+
+/// Split wire format bytes into the schema id and the encoded value
+pub fn decode(bytes: &[u8]) -> Result<(u32, &[u8]), SchemaRegistryError> {
+    match bytes {
+        [MAGIC_BYTE, a, b, c, d, value @ ..] => Ok((u32::from_be_bytes([*a, *b, *c, *d]), value)),
+        [MAGIC_BYTE, ..] => Err(SchemaRegistryError::WireFormat(format!(
+            "{} bytes is too short for the header",
+            bytes.len()
+        ))),
+        [magic, ..] => Err(SchemaRegistryError::WireFormat(format!(
+            "unknown magic byte {}",
+            magic
+        ))),
+        [] => Err(SchemaRegistryError::WireFormat("no bytes".to_owned())),
+    }
+}
+
+/// Append the zigzag varint count and values of a Protobuf message's indexes; `[0]`, the first
+/// message in the schema, is written as a single 0
+pub fn encode_message_indexes(indexes: &[i32], bytes: &mut Vec<u8>) {
+    if indexes == [0] {
+        bytes.push(0);
+        return;
+    }
+    write_varint(indexes.len() as i32, bytes);
+    for index in indexes {
+        write_varint(*index, bytes);
+    }
+}
+
+/// Split the Protobuf message indexes off the front of `bytes`
+pub fn decode_message_indexes(bytes: &[u8]) -> Result<(Vec<i32>, &[u8]), SchemaRegistryError> {
+    let (count, mut bytes) = read_varint(bytes)?;
+    if count == 0 {
+        return Ok((vec![0], bytes));
+    }
+    if count < 0 || count as usize > bytes.len() {
+        return Err(SchemaRegistryError::WireFormat(format!(
+            "invalid message index count {}",
+            count
+        )));
+    }
+    let mut indexes = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (index, rest) = read_varint(bytes)?;
+        indexes.push(index);
+        bytes = rest;
+    }
+
+    Ok((indexes, bytes))
+}
+
+fn write_varint(value: i32, bytes: &mut Vec<u8>) {
+    let mut zigzag = ((value << 1) ^ (value >> 31)) as u32;
+    while zigzag >= 0x80 {
+        bytes.push((zigzag as u8) | 0x80);
+        zigzag >>= 7;
+    }
+    bytes.push(zigzag as u8);
+}
+
+fn read_varint(bytes: &[u8]) -> Result<(i32, &[u8]), SchemaRegistryError> {
+    let mut zigzag: u32 = 0;
+    for (i, b) in bytes.iter().enumerate().take(5) {
+        zigzag |= ((b & 0x7f) as u32) << (7 * i);
+        if b & 0x80 == 0 {
+            let value = ((zigzag >> 1) as i32) ^ -((zigzag & 1) as i32);
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+
+    Err(SchemaRegistryError::WireFormat(
+        "truncated message index varint".to_owned(),
+    ))
+}
+
+/// Serde types as JSON tagged with a JSON Schema's id
+///
+/// Values aren't validated against the schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonSchemaSerializer {
+    schema_id: u32,
+}
+
+impl JsonSchemaSerializer {
+    /// Register `schema` under `subject`, or find its id if it's already registered
+    pub async fn register(
+        client: &SchemaRegistryClient,
+        subject: &str,
+        schema: &str,
+    ) -> Result<Self, SchemaRegistryError> {
+        let schema_id = client.register(subject, &Schema::json(schema)).await?;
+
+        Ok(Self::with_id(schema_id))
+    }
+
+    pub fn with_id(schema_id: u32) -> Self {
+        Self { schema_id }
+    }
+
+    pub fn schema_id(&self) -> u32 {
+        self.schema_id
+    }
+}
+
+impl<T: Serialize + ?Sized> Serializer<T> for JsonSchemaSerializer {
+    fn serialize(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        Ok(encode(self.schema_id, &serde_json::to_vec(value)?))
+    }
+}
+
+/// Serde types from JSON in the wire format, whatever schema id it's tagged with
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonSchemaDeserializer;
+
+impl<T: DeserializeOwned> Deserializer<T> for JsonSchemaDeserializer {
+    fn deserialize(&self, bytes: &[u8]) -> Result<T, CodecError> {
+        let (_, value) = decode(bytes)?;
+
+        Ok(serde_json::from_slice(value)?)
+    }
+}
+
+#[cfg(feature = "avro")]
+pub use avro::{AvroDeserializer, AvroSerializer};
+
+#[cfg(feature = "avro")]
+mod avro {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use apache_avro::Schema as AvroSchema;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::{decode, encode};
+    use crate::codec::{CodecError, Deserializer, Serializer};
+    use crate::schema_registry::{Schema, SchemaRegistryClient, SchemaRegistryError};
+
+    fn parse(schema: &str) -> Result<AvroSchema, SchemaRegistryError> {
+        AvroSchema::parse_str(schema).map_err(|e| SchemaRegistryError::InvalidSchema(e.to_string()))
+    }
+
+    /// Serde types as Avro binary tagged with the schema's id, with the `avro` feature
+    #[derive(Debug, Clone)]
+    pub struct AvroSerializer {
+        schema_id: u32,
+        schema: AvroSchema,
+    }
+
+    impl AvroSerializer {
+        /// Register `schema` under `subject`, or find its id if it's already registered
+        pub async fn register(
+            client: &SchemaRegistryClient,
+            subject: &str,
+            schema: &str,
+        ) -> Result<Self, SchemaRegistryError> {
+            let parsed = parse(schema)?;
+            let schema_id = client.register(subject, &Schema::avro(schema)).await?;
+
+            Ok(Self {
+                schema_id,
+                schema: parsed,
+            })
+        }
+
+        pub fn with_id(schema_id: u32, schema: &str) -> Result<Self, SchemaRegistryError> {
+            Ok(Self {
+                schema_id,
+                schema: parse(schema)?,
+            })
+        }
+
+        pub fn schema_id(&self) -> u32 {
+            self.schema_id
+        }
+    }
+
+    impl<T: Serialize + ?Sized> Serializer<T> for AvroSerializer {
+        fn serialize(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+            let value = apache_avro::to_value(value)?.resolve(&self.schema)?;
+            let datum = apache_avro::to_avro_datum(&self.schema, value)?;
+
+            Ok(encode(self.schema_id, &datum))
+        }
+    }
+
+    /// Serde types from Avro binary in the wire format, with the `avro` feature
+    ///
+    /// Values are decoded with the schema they were written with, fetched by id from the
+    /// registry, and resolved to the reader schema if one is set. The sync Deserializer impl
+    /// can only use schemas that are already cached; `decode` fetches missing ones.
+    #[derive(Debug, Clone)]
+    pub struct AvroDeserializer {
+        client: SchemaRegistryClient,
+        reader_schema: Option<AvroSchema>,
+        writer_schemas: Arc<Mutex<HashMap<u32, AvroSchema>>>,
+    }
+
+    impl AvroDeserializer {
+        pub fn new(client: SchemaRegistryClient) -> Self {
+            Self {
+                client,
+                reader_schema: None,
+                writer_schemas: Arc::new(Mutex::new(HashMap::new())),
+            }
+        }
+
+        /// Resolve values to `schema`, e.g. to read older versions with newer field defaults
+        pub fn with_reader_schema(mut self, schema: &str) -> Result<Self, SchemaRegistryError> {
+            self.reader_schema = Some(parse(schema)?);
+
+            Ok(self)
+        }
+
+        /// Decode `bytes`, fetching the schema they were written with if it isn't cached
+        pub async fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, CodecError> {
+            let (schema_id, _) = decode(bytes)?;
+            if !self.writer_schemas.lock().unwrap().contains_key(&schema_id) {
+                let schema = self.client.schema_by_id(schema_id).await?;
+                self.writer_schemas
+                    .lock()
+                    .unwrap()
+                    .insert(schema_id, parse(&schema.schema)?);
+            }
+
+            self.deserialize(bytes)
+        }
+
+        fn writer_schema(&self, schema_id: u32) -> Result<AvroSchema, SchemaRegistryError> {
+            let mut writer_schemas = self.writer_schemas.lock().unwrap();
+            if let Some(schema) = writer_schemas.get(&schema_id) {
+                return Ok(schema.clone());
+            }
+            let schema = self
+                .client
+                .cached_schema(schema_id)
+                .ok_or(SchemaRegistryError::SchemaNotCached(schema_id))?;
+            let schema = parse(&schema.schema)?;
+            writer_schemas.insert(schema_id, schema.clone());
+
+            Ok(schema)
+        }
+    }
+
+    impl<T: DeserializeOwned> Deserializer<T> for AvroDeserializer {
+        fn deserialize(&self, bytes: &[u8]) -> Result<T, CodecError> {
+            let (schema_id, mut datum) = decode(bytes)?;
+            let writer_schema = self.writer_schema(schema_id)?;
+            let value = apache_avro::from_avro_datum(
+                &writer_schema,
+                &mut datum,
+                self.reader_schema.as_ref(),
+            )?;
+
+            Ok(apache_avro::from_value(&value)?)
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+pub use protobuf::{ProtobufDeserializer, ProtobufSerializer};
+
+#[cfg(feature = "protobuf")]
+mod protobuf {
+    use super::{decode, decode_message_indexes, encode, encode_message_indexes};
+    use crate::codec::{CodecError, Deserializer, Serializer};
+    use crate::schema_registry::{Schema, SchemaRegistryClient, SchemaRegistryError};
+
+    /// prost messages tagged with the schema's id and the message's indexes, with the
+    /// `protobuf` feature
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ProtobufSerializer {
+        schema_id: u32,
+        message_indexes: Vec<i32>,
+    }
+
+    impl ProtobufSerializer {
+        /// Register `schema` under `subject`, or find its id if it's already registered
+        ///
+        /// Values are tagged as the first message in the schema
+        pub async fn register(
+            client: &SchemaRegistryClient,
+            subject: &str,
+            schema: &str,
+        ) -> Result<Self, SchemaRegistryError> {
+            let schema_id = client.register(subject, &Schema::protobuf(schema)).await?;
+
+            Ok(Self::with_id(schema_id))
+        }
+
+        pub fn with_id(schema_id: u32) -> Self {
+            Self {
+                schema_id,
+                message_indexes: vec![0],
+            }
+        }
+
+        /// Tag values as the message at `indexes`, e.g. `[1, 0]` for the first message nested
+        /// in the schema's second message
+        pub fn with_message_indexes(mut self, indexes: Vec<i32>) -> Self {
+            self.message_indexes = indexes;
+
+            self
+        }
+
+        pub fn schema_id(&self) -> u32 {
+            self.schema_id
+        }
+    }
+
+    impl<T: prost::Message> Serializer<T> for ProtobufSerializer {
+        fn serialize(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+            let mut message = Vec::new();
+            encode_message_indexes(&self.message_indexes, &mut message);
+            value.encode(&mut message)?;
+
+            Ok(encode(self.schema_id, &message))
+        }
+    }
+
+    /// prost messages from the wire format, whatever schema id and message indexes they're
+    /// tagged with
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct ProtobufDeserializer;
+
+    impl<T: prost::Message + Default> Deserializer<T> for ProtobufDeserializer {
+        fn deserialize(&self, bytes: &[u8]) -> Result<T, CodecError> {
+            let (_, message) = decode(bytes)?;
+            let (_, message) = decode_message_indexes(message)?;
+
+            Ok(T::decode(message)?)
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use std::fmt::{Debug, Display};
+use std::path::Path;
+
+use rdkafka::error::KafkaError;
+use serde::Deserialize;
+
+use crate::config::EffectiveConfig;
+use crate::error::invalid_config;
+
+/// Placeholder printed instead of secret values
+pub const REDACTED: &str = "[redacted]";
+
+/// librdkafka properties holding secrets that aren't caught by name
+const SENSITIVE_KEYS: &[&str] = &["ssl.key.pem", "ssl_key", "sasl.oauthbearer.config"];
+
+/// Whether the value of librdkafka property `key` is a secret that must not be logged
+pub fn is_sensitive_key(key: &str) -> bool {
+    key.contains("password") || key.contains("secret") || SENSITIVE_KEYS.contains(&key)
+}
+
+/// Protocol used to communicate with brokers
+///
+/// Deserializes from librdkafka's names (`sasl_ssl`), upper-cased or not
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecurityProtocol {
+    /// No encryption, no authentication
+    #[serde(alias = "PLAINTEXT")]
+    Plaintext,
+    /// TLS encryption, optionally with mTLS client authentication
+    #[serde(alias = "SSL")]
+    Ssl,
+    /// SASL authentication without encryption
+    #[serde(alias = "SASL_PLAINTEXT")]
+    SaslPlaintext,
+    /// SASL authentication over TLS
+    #[serde(alias = "SASL_SSL")]
+    SaslSsl,
+}
+
+impl SecurityProtocol {
+    fn uses_tls(&self) -> bool {
+        matches!(self, SecurityProtocol::Ssl | SecurityProtocol::SaslSsl)
+    }
+
+    fn uses_sasl(&self) -> bool {
+        matches!(
+            self,
+            SecurityProtocol::SaslPlaintext | SecurityProtocol::SaslSsl
+        )
+    }
+}
+
+impl Display for SecurityProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecurityProtocol::Plaintext => write!(f, "plaintext"),
+            SecurityProtocol::Ssl => write!(f, "ssl"),
+            SecurityProtocol::SaslPlaintext => write!(f, "sasl_plaintext"),
+            SecurityProtocol::SaslSsl => write!(f, "sasl_ssl"),
+        }
+    }
+}
+
+/// SASL mechanism used to authenticate with brokers
+///
+/// Deserializes from the names librdkafka and rpk use (`SCRAM-SHA-256`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SaslMechanism {
+    /// Username/password sent in the clear; only use with `SaslSsl`
+    #[serde(rename = "PLAIN", alias = "plain")]
+    Plain,
+    /// SCRAM with SHA-256
+    #[serde(rename = "SCRAM-SHA-256", alias = "scram-sha-256")]
+    ScramSha256,
+    /// SCRAM with SHA-512
+    #[serde(rename = "SCRAM-SHA-512", alias = "scram-sha-512")]
+    ScramSha512,
+    /// OAuth bearer tokens, from RedpandaBuilder::with_token_provider or librdkafka's
+    /// `sasl.oauthbearer.*` properties
+    #[serde(rename = "OAUTHBEARER", alias = "oauthbearer")]
+    OAuthBearer,
+}
+
+impl SaslMechanism {
+    fn uses_credentials(&self) -> bool {
+        !matches!(self, SaslMechanism::OAuthBearer)
+    }
+}
+
+impl Display for SaslMechanism {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaslMechanism::Plain => write!(f, "PLAIN"),
+            SaslMechanism::ScramSha256 => write!(f, "SCRAM-SHA-256"),
+            SaslMechanism::ScramSha512 => write!(f, "SCRAM-SHA-512"),
+            SaslMechanism::OAuthBearer => write!(f, "OAUTHBEARER"),
+        }
+    }
+}
+
+/// Where a piece of TLS material comes from
+#[derive(Clone, PartialEq, Eq)]
+pub enum PemSource {
+    /// Path to a PEM file on disk
+    Location(String),
+    /// In-memory PEM string
+    Pem(String),
+}
+
+impl PemSource {
+    /// The property this source sets: `<prefix>.location` or `<prefix>.pem`
+    fn property(&self, prefix: &str) -> String {
+        match self {
+            PemSource::Location(_) => format!("{}.location", prefix),
+            PemSource::Pem(_) => format!("{}.pem", prefix),
+        }
+    }
+
+    /// Apply this source to `config` as `<prefix>.location` or `<prefix>.pem`
+    fn apply(&self, config: &mut EffectiveConfig, prefix: &str) {
+        match self {
+            PemSource::Location(path) => config.set(self.property(prefix), path),
+            PemSource::Pem(pem) => config.set(self.property(prefix), pem),
+        };
+    }
+
+    /// Check that a file exists at the location, or that the in-memory PEM looks like PEM
+    fn validate(&self, prefix: &str) -> Result<(), KafkaError> {
+        match self {
+            PemSource::Location(path) => {
+                if !Path::new(path).is_file() {
+                    return Err(invalid_config(
+                        &self.property(prefix),
+                        path,
+                        "file does not exist",
+                    ));
+                }
+            }
+            PemSource::Pem(pem) => {
+                if !pem.contains("-----BEGIN ") {
+                    return Err(invalid_config(
+                        &self.property(prefix),
+                        REDACTED,
+                        "value is not PEM encoded",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// In-memory PEMs may contain private keys, so they're never printed
+impl Debug for PemSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PemSource::Location(path) => f.debug_tuple("Location").field(path).finish(),
+            PemSource::Pem(_) => f.debug_tuple("Pem").field(&REDACTED).finish(),
+        }
+    }
+}
+
+/// Typed security settings for a RedpandaBuilder
+///
+/// These are validated and merged into the librdkafka config when a client is built
+#[derive(Clone, Default)]
+pub struct SecurityConfig {
+    pub(crate) protocol: Option<SecurityProtocol>,
+    pub(crate) ca: Option<PemSource>,
+    pub(crate) certificate: Option<PemSource>,
+    pub(crate) key: Option<PemSource>,
+    pub(crate) key_password: Option<String>,
+    pub(crate) sasl_mechanism: Option<SaslMechanism>,
+    pub(crate) sasl_username: Option<String>,
+    pub(crate) sasl_password: Option<String>,
+}
+
+impl SecurityConfig {
+    /// Check that the settings are consistent with each other
+    pub fn validate(&self) -> Result<(), KafkaError> {
+        let protocol = self.protocol.unwrap_or(SecurityProtocol::Plaintext);
+
+        let has_tls_material =
+            self.ca.is_some() || self.certificate.is_some() || self.key.is_some();
+        if has_tls_material && !protocol.uses_tls() {
+            return Err(invalid_config(
+                "security.protocol",
+                &protocol.to_string(),
+                "TLS certificates are configured but the protocol doesn't use TLS",
+            ));
+        }
+        // Name the missing property in the form the other one was given in
+        match (&self.certificate, &self.key) {
+            (Some(certificate), None) => {
+                return Err(invalid_config(
+                    &certificate.property("ssl.key"),
+                    "",
+                    "a client certificate requires a private key",
+                ));
+            }
+            (None, Some(key)) => {
+                return Err(invalid_config(
+                    &key.property("ssl.certificate"),
+                    "",
+                    "a private key requires a client certificate",
+                ));
+            }
+            _ => {}
+        }
+        if self.key_password.is_some() && self.key.is_none() {
+            return Err(invalid_config(
+                "ssl.key.password",
+                REDACTED,
+                "a key password requires a private key",
+            ));
+        }
+        if let Some(ca) = &self.ca {
+            ca.validate("ssl.ca")?;
+        }
+        if let Some(certificate) = &self.certificate {
+            certificate.validate("ssl.certificate")?;
+        }
+        if let Some(key) = &self.key {
+            key.validate("ssl.key")?;
+        }
+
+        match (protocol.uses_sasl(), self.sasl_mechanism) {
+            (true, None) => {
+                return Err(invalid_config(
+                    "sasl.mechanism",
+                    "",
+                    "the protocol uses SASL but no mechanism is configured",
+                ))
+            }
+            (false, Some(mechanism)) => {
+                return Err(invalid_config(
+                    "security.protocol",
+                    &protocol.to_string(),
+                    &format!("SASL mechanism {} requires a SASL protocol", mechanism),
+                ))
+            }
+            _ => {}
+        }
+        if self.sasl_mechanism.is_some_and(|m| m.uses_credentials()) {
+            match &self.sasl_username {
+                Some(username) if !username.is_empty() => {}
+                _ => {
+                    return Err(invalid_config(
+                        "sasl.username",
+                        "",
+                        "SASL username is required",
+                    ))
+                }
+            }
+            if self.sasl_password.is_none() {
+                return Err(invalid_config(
+                    "sasl.password",
+                    "",
+                    "SASL password is required",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the settings and write them into `config`
+    pub fn apply(&self, config: &mut EffectiveConfig) -> Result<(), KafkaError> {
+        self.validate()?;
+
+        if let Some(protocol) = self.protocol {
+            config.set("security.protocol", protocol.to_string());
+        }
+        if let Some(ca) = &self.ca {
+            ca.apply(config, "ssl.ca");
+        }
+        if let Some(certificate) = &self.certificate {
+            certificate.apply(config, "ssl.certificate");
+        }
+        if let Some(key) = &self.key {
+            key.apply(config, "ssl.key");
+        }
+        if let Some(password) = &self.key_password {
+            config.set("ssl.key.password", password);
+        }
+        if let Some(mechanism) = self.sasl_mechanism {
+            config.set("sasl.mechanism", mechanism.to_string());
+        }
+        if let Some(username) = &self.sasl_username {
+            config.set("sasl.username", username);
+        }
+        if let Some(password) = &self.sasl_password {
+            config.set("sasl.password", password);
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for SecurityConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecurityConfig")
+            .field("protocol", &self.protocol)
+            .field("ca", &self.ca)
+            .field("certificate", &self.certificate)
+            .field("key", &self.key)
+            .field(
+                "key_password",
+                &self.key_password.as_ref().map(|_| REDACTED),
+            )
+            .field("sasl_mechanism", &self.sasl_mechanism)
+            .field("sasl_username", &self.sasl_username)
+            .field(
+                "sasl_password",
+                &self.sasl_password.as_ref().map(|_| REDACTED),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,109 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Sink, StreamExt};
+
+use crate::context::{RedpandaContext, TracingContext};
+use crate::error::RedpandaError;
+use crate::producer::{DeliveredRecord, RedpandaProducer, RedpandaRecord};
+
+type Delivery = BoxFuture<'static, Result<DeliveredRecord, RedpandaError>>;
+
+/// Sends records with a RedpandaProducer, e.g. to `forward` a stream into a topic
+///
+/// Records are queued in the order they're sent, one at a time, with the producer's
+/// interceptors, encryption, claim check, rate limits and circuit breaker applied like
+/// `send`. At most `max_in_flight` records await delivery: `poll_ready` waits for one to be
+/// delivered when that many are in flight.
+///
+/// A failed delivery is returned by the next `poll_ready` or `poll_flush`, after which the sink
+/// can keep going. `poll_flush` waits for every record in flight. Closing the sink flushes it
+/// but leaves the producer, which may be shared, open.
+pub struct RedpandaSink<C: RedpandaContext = TracingContext> {
+    producer: RedpandaProducer<C>,
+    max_in_flight: usize,
+    enqueuing: Option<BoxFuture<'static, Result<Delivery, RedpandaError>>>,
+    in_flight: FuturesUnordered<Delivery>,
+}
+
+impl<C: RedpandaContext> RedpandaSink<C> {
+    pub fn new(producer: RedpandaProducer<C>, max_in_flight: usize) -> Self {
+        Self {
+            producer,
+            max_in_flight: max_in_flight.max(1),
+            enqueuing: None,
+            in_flight: FuturesUnordered::new(),
+        }
+    }
+
+    pub fn producer(&self) -> &RedpandaProducer<C> {
+        &self.producer
+    }
+
+    /// Records sent to the sink that haven't been delivered or failed yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len() + usize::from(self.enqueuing.is_some())
+    }
+
+    /// Wait for the record being queued to be handed to librdkafka
+    fn poll_enqueued(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), RedpandaError>> {
+        let enqueuing = match &mut self.enqueuing {
+            Some(enqueuing) => enqueuing,
+            None => return Poll::Ready(Ok(())),
+        };
+        let result = futures::ready!(enqueuing.poll_unpin(cx));
+        self.enqueuing = None;
+
+        Poll::Ready(result.map(|delivery| self.in_flight.push(delivery)))
+    }
+
+    /// Collect finished deliveries, returning the first failure
+    fn poll_delivered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), RedpandaError>> {
+        loop {
+            match self.in_flight.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<C: RedpandaContext> Sink<RedpandaRecord> for RedpandaSink<C> {
+    type Error = RedpandaError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_enqueued(cx))?;
+        if let Poll::Ready(Err(e)) = this.poll_delivered(cx) {
+            return Poll::Ready(Err(e));
+        }
+        match this.in_flight.len() < this.max_in_flight {
+            true => Poll::Ready(Ok(())),
+            // poll_delivered registered the waker
+            false => Poll::Pending,
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, record: RedpandaRecord) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let producer = this.producer.clone();
+        this.enqueuing = Some(async move { producer.enqueue(record).await }.boxed());
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_enqueued(cx))?;
+
+        this.poll_delivered(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,469 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rdkafka::Timestamp;
+use thiserror::Error;
+use tracing::{event, Level};
+
+use crate::headers::HeaderMap;
+use crate::producer::{DeliveredRecord, RedpandaRecord};
+
+/// Identifies spool files, followed by the replay cursor
+const MAGIC: &[u8; 8] = b"RPSPOOL2";
+/// Magic bytes and the replay cursor
+const HEADER_LEN: u64 = 16;
+/// Length of an entry's body, CRC32 of the length and CRC32 of the body
+const ENTRY_HEADER_LEN: u64 = 12;
+
+#[derive(Error, Debug)]
+pub enum SpoolError {
+    #[error("spool I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("{0} is not a spool file")]
+    NotASpool(PathBuf),
+    #[error("spool is full, it's limited to {max_bytes} bytes")]
+    Full { max_bytes: u64 },
+    /// The entry's length is corrupt, so where the entries after it start is unknown
+    #[error("spool entry at byte {position} has a corrupt length")]
+    Corrupt { position: u64 },
+    #[error("spool task panicked or was cancelled")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// What happened to a record sent with RedpandaProducer::send_or_spool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    Delivered(DeliveredRecord),
+    /// Written to the spool, to be delivered by a later replay
+    Spooled,
+}
+
+/// Counters of a spool since it was opened, except `pending` and `bytes`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpoolMetrics {
+    /// Records waiting to be replayed
+    pub pending: u64,
+    /// Size of the spool file
+    pub bytes: u64,
+    pub spooled: u64,
+    pub replayed: u64,
+    /// Replayed records the cluster rejected for good, which were dropped
+    pub failed: u64,
+    /// Entries skipped because their CRC didn't match or they were cut short
+    pub corrupted: u64,
+    /// Records that didn't fit under the size limit
+    pub rejected: u64,
+}
+
+/// Entries a replay got past, by what became of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ReplayCounts {
+    pub replayed: u64,
+    pub failed: u64,
+    pub corrupted: u64,
+}
+
+impl ReplayCounts {
+    pub fn total(&self) -> u64 {
+        self.replayed + self.failed + self.corrupted
+    }
+}
+
+#[derive(Debug)]
+struct SpoolFile {
+    file: File,
+    /// Offset of the next entry to replay
+    cursor: u64,
+    /// Offset the next entry is appended at
+    end: u64,
+}
+
+/// Append-only file of records that couldn't be delivered, replayed in order by
+/// RedpandaProducer::replay_spool
+///
+/// Each entry carries a CRC32 of its length and one of its contents; entries with corrupt
+/// contents are skipped and counted, and an entry cut short by a crash is truncated away on
+/// open. A corrupt length hides where the next entry starts, so the spool stops there with
+/// SpoolError::Corrupt rather than drop the entries after it. The replay position is kept in
+/// the file, so a restarted process resumes replaying where it stopped. Space is reclaimed
+/// once every record has been replayed.
+///
+/// File I/O runs on tokio's blocking thread pool, so appending and replaying never block the
+/// async runtime.
+#[derive(Debug)]
+pub struct Spool {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Arc<Mutex<SpoolFile>>,
+    /// Kept apart from the file so reading it never waits for I/O
+    metrics: Arc<Mutex<SpoolMetrics>>,
+    /// Held for the whole of a replay so records are replayed once and in order
+    pub(crate) replay: tokio::sync::Mutex<()>,
+}
+
+impl Spool {
+    /// Open or create the spool at `path`, holding at most `max_bytes`
+    ///
+    /// An empty file is made a spool; any other file that doesn't start with the spool's magic
+    /// bytes is NotASpool. A spool with an entry whose length is corrupt is Corrupt and left
+    /// as it is.
+    pub fn open(path: impl AsRef<Path>, max_bytes: u64) -> Result<Self, SpoolError> {
+        let path = path.as_ref().to_owned();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let len = file.metadata()?.len();
+        let cursor = if len == 0 {
+            file.write_all(MAGIC)?;
+            file.write_all(&HEADER_LEN.to_le_bytes())?;
+            file.sync_all()?;
+            HEADER_LEN
+        } else {
+            if len < HEADER_LEN {
+                return Err(SpoolError::NotASpool(path));
+            }
+            let mut header = [0; HEADER_LEN as usize];
+            file.read_exact(&mut header)?;
+            if &header[..8] != MAGIC {
+                return Err(SpoolError::NotASpool(path));
+            }
+            u64::from_le_bytes(header[8..].try_into().unwrap()).clamp(HEADER_LEN, len)
+        };
+
+        let mut spool = SpoolFile {
+            file,
+            cursor,
+            end: HEADER_LEN,
+        };
+        let mut metrics = SpoolMetrics::default();
+        spool.recover(len, &mut metrics)?;
+        metrics.bytes = spool.end;
+        event!(
+            Level::INFO,
+            "Opened spool {} with {} pending records",
+            path.display(),
+            metrics.pending
+        );
+
+        Ok(Self {
+            path,
+            max_bytes,
+            file: Arc::new(Mutex::new(spool)),
+            metrics: Arc::new(Mutex::new(metrics)),
+            replay: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn metrics(&self) -> SpoolMetrics {
+        *self.metrics.lock().unwrap()
+    }
+
+    /// Whether there are records waiting to be replayed
+    pub fn is_empty(&self) -> bool {
+        self.metrics.lock().unwrap().pending == 0
+    }
+
+    /// Durably append `record`
+    pub async fn append(&self, record: &RedpandaRecord) -> Result<(), SpoolError> {
+        let body = encode(record);
+        let max_bytes = self.max_bytes;
+        self.blocking(move |spool, metrics| {
+            if spool.end + ENTRY_HEADER_LEN + body.len() as u64 > max_bytes {
+                metrics.lock().unwrap().rejected += 1;
+                return Err(SpoolError::Full { max_bytes });
+            }
+            let len = (body.len() as u32).to_le_bytes();
+            let mut entry = Vec::with_capacity(ENTRY_HEADER_LEN as usize + body.len());
+            entry.extend_from_slice(&len);
+            entry.extend_from_slice(&crc32fast::hash(&len).to_le_bytes());
+            entry.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+            entry.extend_from_slice(&body);
+            let end = spool.end;
+            spool.file.seek(SeekFrom::Start(end))?;
+            spool.file.write_all(&entry)?;
+            spool.file.sync_data()?;
+            spool.end += entry.len() as u64;
+            let mut metrics = metrics.lock().unwrap();
+            metrics.pending += 1;
+            metrics.spooled += 1;
+            metrics.bytes = spool.end;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Where the replay resumes
+    pub(crate) async fn cursor(&self) -> Result<u64, SpoolError> {
+        self.blocking(|spool, _| Ok(spool.cursor)).await
+    }
+
+    /// The entry at `position` and the position after it, None past the last entry
+    ///
+    /// The record is None if the entry is corrupt. Reading doesn't move the replay position,
+    /// so a replay can read ahead of the records it has sent.
+    pub(crate) async fn read(
+        &self,
+        position: u64,
+    ) -> Result<Option<(Option<RedpandaRecord>, u64)>, SpoolError> {
+        self.blocking(move |spool, _| match position < spool.end {
+            true => {
+                let (next, record) = spool.read_entry(position)?;
+                if record.is_none() {
+                    event!(Level::WARN, "Skipping corrupt spool entry at {}", position);
+                }
+                Ok(Some((record, next)))
+            }
+            false => Ok(None),
+        })
+        .await
+    }
+
+    /// Move the replay position to `next`, past the entries in `counts`, emptying the spool if
+    /// they were the last ones
+    pub(crate) async fn advance(&self, next: u64, counts: ReplayCounts) -> Result<(), SpoolError> {
+        self.blocking(move |spool, metrics| {
+            if next >= spool.end {
+                spool.clear()?;
+            } else {
+                spool.set_cursor(next)?;
+            }
+            let mut metrics = metrics.lock().unwrap();
+            metrics.pending = metrics.pending.saturating_sub(counts.total());
+            metrics.bytes = spool.end;
+            metrics.replayed += counts.replayed;
+            metrics.failed += counts.failed;
+            metrics.corrupted += counts.corrupted;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Run `f` with the file locked on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T, SpoolError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut SpoolFile, &Mutex<SpoolMetrics>) -> Result<T, SpoolError> + Send + 'static,
+    {
+        let file = self.file.clone();
+        let metrics = self.metrics.clone();
+
+        tokio::task::spawn_blocking(move || f(&mut file.lock().unwrap(), &metrics)).await?
+    }
+}
+
+impl SpoolFile {
+    /// Count the pending entries and truncate an entry cut short by a crash
+    ///
+    /// Only the last entry can have been cut short: its length is intact but runs past the end
+    /// of the file, or the file was extended with zeros that were never written over. Any
+    /// other corrupt length fails with Corrupt, leaving the file as it is.
+    fn recover(&mut self, len: u64, metrics: &mut SpoolMetrics) -> Result<(), SpoolError> {
+        let mut position = HEADER_LEN;
+        while position < len {
+            let header_fits = position + ENTRY_HEADER_LEN <= len;
+            let next = match header_fits {
+                true => self.entry_end(position)?,
+                false => None,
+            };
+            let next = match next {
+                Some(next) if next <= len => next,
+                None if header_fits && !self.is_zeroed(position, len)? => {
+                    event!(
+                        Level::ERROR,
+                        "Spool entry at {} has a corrupt length, not reading past it",
+                        position
+                    );
+                    return Err(SpoolError::Corrupt { position });
+                }
+                _ => {
+                    event!(
+                        Level::WARN,
+                        "Truncating incomplete spool entry at {}",
+                        position
+                    );
+                    metrics.corrupted += 1;
+                    self.file.set_len(position)?;
+                    break;
+                }
+            };
+            // A cursor inside an entry moves to the next one
+            if position < self.cursor && next > self.cursor {
+                self.cursor = next;
+            }
+            if position >= self.cursor {
+                metrics.pending += 1;
+            }
+            position = next;
+        }
+        self.end = position;
+        self.cursor = self.cursor.min(self.end);
+
+        Ok(())
+    }
+
+    /// The position after the entry at `position`, None if its length is corrupt
+    fn entry_end(&mut self, position: u64) -> Result<Option<u64>, SpoolError> {
+        Ok(self
+            .read_header(position)?
+            .map(|(len, _)| position + ENTRY_HEADER_LEN + len))
+    }
+
+    /// The length and body CRC of the entry at `position`, None if its length is corrupt
+    fn read_header(&mut self, position: u64) -> Result<Option<(u64, u32)>, SpoolError> {
+        let mut header = [0; ENTRY_HEADER_LEN as usize];
+        self.file.seek(SeekFrom::Start(position))?;
+        self.file.read_exact(&mut header)?;
+        let field =
+            |range: std::ops::Range<usize>| u32::from_le_bytes(header[range].try_into().unwrap());
+        if crc32fast::hash(&header[..4]) != field(4..8) {
+            return Ok(None);
+        }
+
+        Ok(Some((field(0..4) as u64, field(8..12))))
+    }
+
+    /// Whether every byte from `position` to `len` is zero
+    fn is_zeroed(&mut self, position: u64, len: u64) -> Result<bool, SpoolError> {
+        let mut rest = Vec::new();
+        self.file.seek(SeekFrom::Start(position))?;
+        (&mut self.file)
+            .take(len - position)
+            .read_to_end(&mut rest)?;
+
+        Ok(rest.iter().all(|byte| *byte == 0))
+    }
+
+    /// The position after the entry at `position`, and its record unless it's corrupt
+    fn read_entry(&mut self, position: u64) -> Result<(u64, Option<RedpandaRecord>), SpoolError> {
+        let (len, crc) = self
+            .read_header(position)?
+            .ok_or(SpoolError::Corrupt { position })?;
+        let next = (position + ENTRY_HEADER_LEN + len).min(self.end);
+        let mut body = vec![0; (next - position - ENTRY_HEADER_LEN) as usize];
+        self.file.read_exact(&mut body)?;
+        if body.len() as u64 != len || crc32fast::hash(&body) != crc {
+            return Ok((next, None));
+        }
+
+        Ok((next, decode(&body)))
+    }
+
+    /// Drop every entry once they've all been replayed
+    fn clear(&mut self) -> Result<(), SpoolError> {
+        self.file.set_len(HEADER_LEN)?;
+        self.end = HEADER_LEN;
+        self.set_cursor(HEADER_LEN)?;
+        self.file.sync_all()?;
+
+        Ok(())
+    }
+
+    fn set_cursor(&mut self, cursor: u64) -> Result<(), SpoolError> {
+        self.file.seek(SeekFrom::Start(MAGIC.len() as u64))?;
+        self.file.write_all(&cursor.to_le_bytes())?;
+        self.file.sync_data()?;
+        self.cursor = cursor;
+
+        Ok(())
+    }
+}
+
+fn encode(record: &RedpandaRecord) -> Vec<u8> {
+    let mut body = Vec::with_capacity(64 + record.payload().len());
+    put_bytes(&mut body, record.topic().as_bytes());
+    body.extend_from_slice(&record.partition().unwrap_or(-1).to_le_bytes());
+    body.extend_from_slice(&record.timestamp().to_millis().unwrap_or(-1).to_le_bytes());
+    put_optional_bytes(&mut body, record.key());
+    put_bytes(&mut body, record.payload());
+    let headers = record.headers();
+    body.extend_from_slice(&(headers.len() as u32).to_le_bytes());
+    for (key, value) in headers.iter() {
+        put_bytes(&mut body, key.as_bytes());
+        put_optional_bytes(&mut body, value);
+    }
+
+    body
+}
+
+fn put_bytes(body: &mut Vec<u8>, bytes: &[u8]) {
+    body.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    body.extend_from_slice(bytes);
+}
+
+fn put_optional_bytes(body: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        Some(bytes) => {
+            body.push(1);
+            put_bytes(body, bytes);
+        }
+        None => body.push(0),
+    }
+}
+
+/// None if the body doesn't decode, which the CRC makes unlikely
+fn decode(body: &[u8]) -> Option<RedpandaRecord> {
+    let mut reader = Reader(body);
+    let topic = std::str::from_utf8(reader.bytes()?).ok()?;
+    let partition = i32::from_le_bytes(reader.take(4)?.try_into().ok()?);
+    let timestamp = i64::from_le_bytes(reader.take(8)?.try_into().ok()?);
+    let key = reader.optional_bytes()?.map(<[u8]>::to_vec);
+    let payload = reader.bytes()?.to_vec();
+    let mut headers = HeaderMap::new();
+    for _ in 0..reader.u32()? {
+        let key = std::str::from_utf8(reader.bytes()?).ok()?;
+        headers.append(key, reader.optional_bytes()?);
+    }
+    let headers = (!headers.is_empty()).then(|| headers.to_owned_headers());
+
+    let mut record =
+        RedpandaRecord::new(topic, key, payload, headers).with_timestamp(match timestamp {
+            -1 => Timestamp::NotAvailable,
+            millis => Timestamp::CreateTime(millis),
+        });
+    if partition >= 0 {
+        record = record.with_partition(partition);
+    }
+
+    Some(record)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn optional_bytes(&mut self) -> Option<Option<&'a [u8]>> {
+        match self.take(1)?[0] {
+            0 => Some(None),
+            _ => self.bytes().map(Some),
+        }
+    }
+}
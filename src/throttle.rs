@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::error::RedpandaError;
+
+/// Longest a record waits for tokens, so a rate too small for its cost can't overflow the
+/// wait. The tokens are still taken, delaying the records after it.
+pub const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Token-bucket limits on records and bytes (key plus payload) per second
+///
+/// Each bucket holds one second's worth of tokens, so that much can be sent in a burst after
+/// an idle period
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimit {
+    pub records_per_second: Option<f64>,
+    pub bytes_per_second: Option<f64>,
+}
+
+impl RateLimit {
+    pub fn records(per_second: f64) -> Self {
+        Self::default().with_records(per_second)
+    }
+
+    pub fn bytes(per_second: f64) -> Self {
+        Self::default().with_bytes(per_second)
+    }
+
+    pub fn with_records(mut self, per_second: f64) -> Self {
+        self.records_per_second = Some(per_second);
+
+        self
+    }
+
+    pub fn with_bytes(mut self, per_second: f64) -> Self {
+        self.bytes_per_second = Some(per_second);
+
+        self
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    /// Negative while sends are waiting for tokens they've already taken
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            refilled: now,
+        }
+    }
+
+    /// Take `cost` tokens and return how long to wait until they've been refilled, at most
+    /// MAX_RATE_LIMIT_WAIT
+    fn reserve(&mut self, cost: f64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= cost;
+        match self.tokens < 0.0 {
+            true => Duration::try_from_secs_f64(-self.tokens / self.rate)
+                .map_or(MAX_RATE_LIMIT_WAIT, |wait| wait.min(MAX_RATE_LIMIT_WAIT)),
+            false => Duration::ZERO,
+        }
+    }
+
+    /// Whether `cost` tokens can be taken without waiting. A cost over the rate only needs a
+    /// full bucket, or it could never be taken.
+    fn has(&mut self, cost: f64, now: Instant) -> bool {
+        self.refill(now);
+
+        self.tokens >= cost.min(self.rate)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled = now;
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    records: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        let bucket = |rate: Option<f64>| {
+            rate.filter(|rate| *rate > 0.0)
+                .map(|rate| TokenBucket::new(rate, now))
+        };
+
+        Self {
+            records: bucket(limit.records_per_second),
+            bytes: bucket(limit.bytes_per_second),
+        }
+    }
+
+    fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        let records = self.records.as_mut().map(|bucket| bucket.reserve(1.0, now));
+        let bytes = self
+            .bytes
+            .as_mut()
+            .map(|bucket| bucket.reserve(bytes as f64, now));
+
+        records.unwrap_or_default().max(bytes.unwrap_or_default())
+    }
+
+    fn has(&mut self, bytes: usize, now: Instant) -> bool {
+        let records = self
+            .records
+            .as_mut()
+            .is_none_or(|bucket| bucket.has(1.0, now));
+        let bytes = self
+            .bytes
+            .as_mut()
+            .is_none_or(|bucket| bucket.has(bytes as f64, now));
+
+        records && bytes
+    }
+}
+
+/// Delays sends that go over a global limit or their topic's limit
+///
+/// Shared by every clone of a producer. Records must fit both the global and the topic limit.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limit: Option<RateLimit>,
+    topic_limits: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<Option<String>, Buckets>>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit every record, whatever its topic
+    pub fn with_limit(mut self, limit: RateLimit) -> Self {
+        self.limit = Some(limit);
+
+        self
+    }
+
+    /// Limit records for `topic`
+    pub fn with_topic_limit(mut self, topic: &str, limit: RateLimit) -> Self {
+        self.topic_limits.insert(topic.to_owned(), limit);
+
+        self
+    }
+
+    /// Take tokens for a record of `bytes` bytes for `topic` and return how long to wait
+    /// before sending it
+    pub fn reserve(&self, topic: &str, bytes: usize) -> Duration {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let global = self.limit.map(|limit| {
+            buckets
+                .entry(None)
+                .or_insert_with(|| Buckets::new(limit, now))
+                .reserve(bytes, now)
+        });
+        let topic = self.topic_limits.get(topic).map(|&limit| {
+            buckets
+                .entry(Some(topic.to_owned()))
+                .or_insert_with(|| Buckets::new(limit, now))
+                .reserve(bytes, now)
+        });
+
+        global.unwrap_or_default().max(topic.unwrap_or_default())
+    }
+
+    /// Take tokens for a record of `bytes` bytes for `topic` if it can be sent without waiting,
+    /// else take none and return false
+    pub fn try_reserve(&self, topic: &str, bytes: usize) -> bool {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let topic_limit = self.topic_limits.get(topic);
+        let limits: Vec<_> = (self.limit.map(|limit| (None, limit)).into_iter())
+            .chain(topic_limit.map(|&limit| (Some(topic.to_owned()), limit)))
+            .collect();
+        let has = limits.iter().all(|(key, limit)| {
+            buckets
+                .entry(key.clone())
+                .or_insert_with(|| Buckets::new(*limit, now))
+                .has(bytes, now)
+        });
+        if has {
+            for (key, _) in limits {
+                buckets.get_mut(&key).unwrap().reserve(bytes, now);
+            }
+        }
+
+        has
+    }
+
+    /// Wait until a record of `bytes` bytes for `topic` can be sent
+    pub async fn acquire(&self, topic: &str, bytes: usize) {
+        let wait = self.reserve(topic, bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// State of a CircuitBreaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Records are sent
+    Closed,
+    /// Records fail with RedpandaError::CircuitOpen
+    Open,
+    /// The cooldown is over and the next record is sent as a probe
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    failures: u32,
+    opened: Option<Instant>,
+    probe_sent: Option<Instant>,
+}
+
+/// Fails sends fast after consecutive delivery failures
+///
+/// The circuit opens after `failure_threshold` consecutive failed deliveries. After `cooldown`
+/// one record is let through as a probe: the circuit closes if it's delivered and opens again
+/// if it fails. A probe that never reports back is replaced by another one after `cooldown`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    circuit: Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            circuit: Mutex::new(Circuit::default()),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.retry_in(&self.circuit.lock().unwrap()) {
+            None => CircuitState::Closed,
+            Some(retry_in) if !retry_in.is_zero() => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Consecutive failed deliveries
+    pub fn failures(&self) -> u32 {
+        self.circuit.lock().unwrap().failures
+    }
+
+    /// How long until a probe can be sent, None while the circuit is closed
+    fn retry_in(&self, circuit: &Circuit) -> Option<Duration> {
+        let since = circuit.probe_sent.or(circuit.opened)?;
+
+        Some(self.cooldown.saturating_sub(since.elapsed()))
+    }
+
+    /// Ok if a record may be sent, CircuitOpen if not
+    pub(crate) fn admit(&self) -> Result<(), RedpandaError> {
+        let mut circuit = self.circuit.lock().unwrap();
+        match self.retry_in(&circuit) {
+            None => Ok(()),
+            Some(retry_in) if !retry_in.is_zero() => Err(RedpandaError::CircuitOpen {
+                failures: circuit.failures,
+                retry_in,
+            }),
+            Some(_) => {
+                circuit.probe_sent = Some(Instant::now());
+                Ok(())
+            }
+        }
+    }
+
+    /// Count a delivery, opening or closing the circuit
+    pub(crate) fn record(&self, delivered: bool) {
+        let mut circuit = self.circuit.lock().unwrap();
+        if delivered {
+            *circuit = Circuit::default();
+            return;
+        }
+        circuit.failures = circuit.failures.saturating_add(1);
+        let probe_failed = circuit.probe_sent.is_some();
+        if probe_failed || (circuit.opened.is_none() && circuit.failures >= self.failure_threshold)
+        {
+            circuit.opened = Some(Instant::now());
+            circuit.probe_sent = None;
+        }
+    }
+}
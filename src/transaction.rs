@@ -0,0 +1,160 @@
+use std::future::Future;
+
+use rdkafka::consumer::Consumer;
+use rdkafka::error::KafkaError;
+use rdkafka::producer::Producer;
+use rdkafka::util::Timeout;
+use rdkafka::TopicPartitionList;
+use tracing::{event, instrument, Level};
+
+use crate::consumer::RedpandaConsumer;
+use crate::context::RedpandaContext;
+use crate::error::{transaction_error, RedpandaError, TransactionErrorKind};
+use crate::producer::{ContextProducer, RedpandaProducer};
+
+/// Times `transaction` calls commit or abort when they fail with a retriable error
+const END_TRANSACTION_ATTEMPTS: usize = 3;
+
+/// Transactions need a producer built with RedpandaBuilder::set_transactional_id. Operations
+/// that wait on the cluster run on tokio's blocking thread pool, bounded by the builder's
+/// creation timeout.
+impl<C: RedpandaContext> RedpandaProducer<C> {
+    /// Register the transactional.id with the cluster, fencing off older producers using it and
+    /// aborting or completing their open transaction
+    ///
+    /// Call once, before the first transaction
+    #[instrument(skip(self))]
+    pub async fn init_transactions(&self) -> Result<(), RedpandaError> {
+        self.blocking(|producer, timeout| producer.init_transactions(timeout))
+            .await
+    }
+
+    /// Start a transaction; records sent until it's committed or aborted belong to it
+    pub fn begin_transaction(&self) -> Result<(), RedpandaError> {
+        self.producer.begin_transaction().map_err(transaction_error)
+    }
+
+    /// Flush the transaction's records and commit them
+    #[instrument(skip(self))]
+    pub async fn commit_transaction(&self) -> Result<(), RedpandaError> {
+        self.blocking(|producer, timeout| producer.commit_transaction(timeout))
+            .await
+    }
+
+    /// Abort the transaction; read_committed consumers never see its records
+    #[instrument(skip(self))]
+    pub async fn abort_transaction(&self) -> Result<(), RedpandaError> {
+        self.blocking(|producer, timeout| producer.abort_transaction(timeout))
+            .await
+    }
+
+    /// Commit `offsets` for `consumer`'s group as part of the current transaction, so consumed
+    /// input and produced output are committed together
+    ///
+    /// Offsets are those of the next messages to consume, i.e. the last processed offset + 1
+    #[instrument(skip(self, offsets, consumer))]
+    pub async fn send_offsets_to_transaction<D: RedpandaContext>(
+        &self,
+        offsets: &TopicPartitionList,
+        consumer: &RedpandaConsumer<D>,
+    ) -> Result<(), RedpandaError> {
+        let group_metadata =
+            consumer
+                .consumer
+                .group_metadata()
+                .ok_or_else(|| RedpandaError::ClientConfig {
+                    key: "group.id".to_owned(),
+                    message: "the consumer isn't part of a consumer group".to_owned(),
+                })?;
+        let offsets = offsets.clone();
+        self.blocking(move |producer, timeout| {
+            producer.send_offsets_to_transaction(&offsets, &group_metadata, timeout)
+        })
+        .await
+    }
+
+    /// Run `f` in a transaction: commit if it returns Ok, abort if it returns Err
+    ///
+    /// `f` gets a clone of this producer to send the transaction's records with. Commits that
+    /// fail with an abortable error are aborted, and retriable commit and abort failures are
+    /// retried a few times. The error returned is `f`'s if it failed, otherwise the commit's;
+    /// check its `transaction_error_kind` to tell whether the producer is still usable.
+    ///
+    /// ```ignore
+    /// producer
+    ///     .transaction(|tx| async move {
+    ///         tx.send_result(&record).map_err(|(e, _)| e)?.await;
+    ///         Ok::<_, RedpandaError>(())
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn transaction<F, Fut, T, E>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(RedpandaProducer<C>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<RedpandaError>,
+    {
+        self.begin_transaction()?;
+
+        let value = match f(self.clone()).await {
+            Ok(value) => value,
+            Err(e) => {
+                if let Err(abort_error) = self.end_transaction(false).await {
+                    event!(
+                        Level::ERROR,
+                        "Failed to abort transaction: {:?}",
+                        abort_error
+                    );
+                }
+                return Err(e);
+            }
+        };
+
+        match self.end_transaction(true).await {
+            Ok(()) => Ok(value),
+            Err(e) if e.transaction_error_kind() == Some(TransactionErrorKind::Abortable) => {
+                event!(
+                    Level::WARN,
+                    "Aborting transaction after failed commit: {:?}",
+                    e
+                );
+                self.end_transaction(false).await?;
+                Err(e.into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Commit or abort, retrying retriable errors
+    async fn end_transaction(&self, commit: bool) -> Result<(), RedpandaError> {
+        let mut attempt = 1;
+        loop {
+            let result = match commit {
+                true => self.commit_transaction().await,
+                false => self.abort_transaction().await,
+            };
+            match result {
+                Err(e)
+                    if e.transaction_error_kind() == Some(TransactionErrorKind::Retriable)
+                        && attempt < END_TRANSACTION_ATTEMPTS =>
+                {
+                    event!(Level::WARN, "Retrying transaction end: {:?}", e);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Run a blocking producer call on the blocking thread pool
+    async fn blocking<F>(&self, f: F) -> Result<(), RedpandaError>
+    where
+        F: FnOnce(&ContextProducer<C>, Timeout) -> Result<(), KafkaError> + Send + 'static,
+    {
+        let producer = self.producer.clone();
+        let timeout = self.request_timeout;
+        tokio::task::spawn_blocking(move || f(&producer, timeout))
+            .await?
+            .map_err(transaction_error)
+    }
+}